@@ -0,0 +1,165 @@
+use serde::{Serialize, de::DeserializeOwned};
+use super::*;
+
+// every encoded value starts with the same fixed header:
+// 4 magic bytes, a little endian u16 schema version, and a single byte saying what kind of value follows
+// the rest is the value itself encoded with postcard
+// bump BINARY_SCHEMA_VERSION whenever any encoded type changes shape, old data must never be silently misread
+pub const BINARY_MAGIC: [u8; 4] = *b"ADPY";
+pub const BINARY_SCHEMA_VERSION: u16 = 19;
+const HEADER_LENGTH: usize = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryKind {
+	State = 1,
+	Snapshot = 2,
+	Entry = 3,
+	Log = 4,
+}
+
+impl BinaryKind {
+	fn from_byte(byte: u8) -> Option<BinaryKind> {
+		match byte {
+			1 => Some(BinaryKind::State),
+			2 => Some(BinaryKind::Snapshot),
+			3 => Some(BinaryKind::Entry),
+			4 => Some(BinaryKind::Log),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+	TooShort{ length: usize },
+	BadMagic{ found: [u8; 4] },
+	UnsupportedVersion{ found: u16, supported: u16 },
+	UnknownKind{ found: u8 },
+	MismatchedKind{ found: BinaryKind, expected: BinaryKind },
+	Payload(postcard::Error),
+	TrailingBytes{ count: usize },
+}
+
+pub fn encode_state(state: &PolityState) -> Result<Vec<u8>, postcard::Error> { encode(BinaryKind::State, state) }
+pub fn decode_state(bytes: &[u8]) -> Result<PolityState, DecodeError> { decode(BinaryKind::State, bytes) }
+
+pub fn encode_snapshot(snapshot: &PolitySnapshot) -> Result<Vec<u8>, postcard::Error> { encode(BinaryKind::Snapshot, snapshot) }
+pub fn decode_snapshot(bytes: &[u8]) -> Result<PolitySnapshot, DecodeError> { decode(BinaryKind::Snapshot, bytes) }
+
+pub fn encode_entry(entry: &PolityActionEntry) -> Result<Vec<u8>, postcard::Error> { encode(BinaryKind::Entry, entry) }
+pub fn decode_entry(bytes: &[u8]) -> Result<PolityActionEntry, DecodeError> { decode(BinaryKind::Entry, bytes) }
+
+// a whole log segment, which keeps its first_position so compacted logs decode to the same positions
+pub fn encode_log(log: &PolityActionLog) -> Result<Vec<u8>, postcard::Error> { encode(BinaryKind::Log, log) }
+pub fn decode_log(bytes: &[u8]) -> Result<PolityActionLog, DecodeError> { decode(BinaryKind::Log, bytes) }
+
+fn encode<T: Serialize>(kind: BinaryKind, value: &T) -> Result<Vec<u8>, postcard::Error> {
+	let mut bytes = Vec::from(BINARY_MAGIC);
+	bytes.extend_from_slice(&BINARY_SCHEMA_VERSION.to_le_bytes());
+	bytes.push(kind as u8);
+	bytes.extend(postcard::to_allocvec(value)?);
+	Ok(bytes)
+}
+
+fn decode<T: DeserializeOwned>(expected: BinaryKind, bytes: &[u8]) -> Result<T, DecodeError> {
+	if bytes.len() < HEADER_LENGTH {
+		return Err(DecodeError::TooShort{ length: bytes.len() });
+	}
+
+	let magic = [bytes[0], bytes[1], bytes[2], bytes[3]];
+	if magic != BINARY_MAGIC {
+		return Err(DecodeError::BadMagic{ found: magic });
+	}
+	let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+	if version != BINARY_SCHEMA_VERSION {
+		return Err(DecodeError::UnsupportedVersion{ found: version, supported: BINARY_SCHEMA_VERSION });
+	}
+	let found = BinaryKind::from_byte(bytes[6]).ok_or(DecodeError::UnknownKind{ found: bytes[6] })?;
+	if found != expected {
+		return Err(DecodeError::MismatchedKind{ found, expected });
+	}
+
+	let (value, rest) = postcard::take_from_bytes(&bytes[HEADER_LENGTH..]).map_err(DecodeError::Payload)?;
+	if !rest.is_empty() {
+		return Err(DecodeError::TrailingBytes{ count: rest.len() });
+	}
+	Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn make_state() -> PolityState {
+		let mut state = PolityState::build().with_resource().finish();
+		state.apply_changes(vec![
+			PolityStateChange::InsertPerson{ person_id: 1, given_weight: "10.5".parse().unwrap() },
+			PolityStateChange::InsertCandidacy{ candidacy: StorageCandidacy{
+				id: 10, owner_id: 1, election_id: 0, status: CandidacyStatus::Election("0.333".parse().unwrap()),
+				content: CandidacyContent::Document{ pitch: "pitch".into(), body: "body".into(), sub_elections: vec![] },
+			} },
+			PolityStateChange::SetResourceAllocations{ voter_id: 1, allocations: vec![
+				ResourceAllocation{ election_id: 0, candidacy_id: 10, weight: "0.1000000000000000000000000001".parse().unwrap(), fallback_candidacy_ids: vec![] },
+			] },
+			PolityStateChange::SetResourceScoreAllocations{ voter_id: 1, allocations: vec![
+				ResourceScoreAllocation{ election_id: 0, approve_weight: 1.into(), disapprove_weight: 2.into(), scores: HashMap::from([(10, (-1).into())]), fallback_candidacy_ids: HashMap::new() },
+			] },
+		]);
+		state
+	}
+
+	#[test]
+	fn test_round_trips() {
+		let state = make_state();
+		assert_eq!(decode_state(&encode_state(&state).unwrap()).unwrap(), state);
+
+		let snapshot = PolitySnapshot{ position: 42, state };
+		assert_eq!(decode_snapshot(&encode_snapshot(&snapshot).unwrap()).unwrap(), snapshot);
+
+		let entry = PolityActionEntry{ occurred_at: timestamp(7), change: PolityAction::ExitCandidacy{ candidacy_id: 10 } };
+		let decoded = decode_entry(&encode_entry(&entry).unwrap()).unwrap();
+		assert_eq!(decoded.occurred_at, timestamp(7));
+		assert!(matches!(decoded.change, PolityAction::ExitCandidacy{ candidacy_id: 10 }));
+
+		let mut log = PolityActionLog::new();
+		log.append(entry.clone());
+		log.append(entry);
+		log.compact(&PolitySnapshot{ position: 1, state: PolityState::build().finish() });
+		let decoded = decode_log(&encode_log(&log).unwrap()).unwrap();
+		assert_eq!(decoded.first_position(), 1);
+		assert_eq!(decoded.len(), 1);
+	}
+
+	#[test]
+	fn test_rejects_bad_headers() {
+		let bytes = encode_state(&make_state()).unwrap();
+
+		assert_eq!(decode_state(&bytes[..3]).unwrap_err(), DecodeError::TooShort{ length: 3 });
+
+		let mut bad_magic = bytes.clone();
+		bad_magic[0] = b'X';
+		assert_eq!(decode_state(&bad_magic).unwrap_err(), DecodeError::BadMagic{ found: *b"XDPY" });
+
+		let mut future_version = bytes.clone();
+		future_version[4..6].copy_from_slice(&(BINARY_SCHEMA_VERSION + 1).to_le_bytes());
+		assert_eq!(
+			decode_state(&future_version).unwrap_err(),
+			DecodeError::UnsupportedVersion{ found: BINARY_SCHEMA_VERSION + 1, supported: BINARY_SCHEMA_VERSION },
+		);
+
+		let mut unknown_kind = bytes.clone();
+		unknown_kind[6] = 99;
+		assert_eq!(decode_state(&unknown_kind).unwrap_err(), DecodeError::UnknownKind{ found: 99 });
+
+		assert_eq!(
+			decode_snapshot(&bytes).unwrap_err(),
+			DecodeError::MismatchedKind{ found: BinaryKind::State, expected: BinaryKind::Snapshot },
+		);
+
+		let mut trailing = bytes.clone();
+		trailing.push(0);
+		assert_eq!(decode_state(&trailing).unwrap_err(), DecodeError::TrailingBytes{ count: 1 });
+
+		assert!(matches!(decode_state(&bytes[..HEADER_LENGTH + 1]).unwrap_err(), DecodeError::Payload(_)));
+	}
+}
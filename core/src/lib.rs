@@ -1,69 +1,296 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use core::hash::{Hash, Hasher};
 use core::borrow::Borrow;
-// use chrono::{DateTime as ChronoDateTime, Utc}
 use rust_decimal::prelude::*;
 
-// type DateTime = chrono::DateTime<chrono::Utc>;
-type DateTime = i64;
-type Weight = Decimal;
+mod polity;
+pub use polity::{Polity, PolityActionOutcome, BatchActionErrors};
+mod log;
+pub use log::{PolityActionLog, PolitySnapshot, ReplayFailure, Replay, RestoreError};
+mod preview;
+pub use preview::{preview_recalculation, RecalculationPreview, RecalculationSummary, WinnerChange};
+#[cfg(feature = "binary")]
+pub mod encoding;
 
-#[derive(Debug)]
-struct PolityActionEntry {
-	occurred_at: DateTime,
-	change: PolityAction,
+pub type DateTime = chrono::DateTime<chrono::Utc>;
+pub type Duration = chrono::Duration;
+pub type Weight = Decimal;
+
+#[cfg(test)]
+pub(crate) fn timestamp(seconds: i64) -> DateTime {
+	DateTime::from_timestamp(seconds, 0).unwrap()
+}
+
+// a single seat resource office with no nomination stage, tests change what they need with struct update syntax
+#[cfg(test)]
+pub(crate) fn office_election(id: usize) -> InputElection {
+	InputElection {
+		id, title: "".into(), description: "".into(), kind: ElectionKind::Office,
+		selection_method: SelectionMethod::Resource{ scale_quadratically: false },
+		seats: 1,
+		carries_over: false,
+		nomination_fill_method: NominationFillMethod::None,
+		election_fill_method: ElectionFillMethod::Constant(10.into()),
+		negative_buckets: NegativeBucketsKind::None,
+		update_frequency: None,
+		vacancy_policy: VacancyPolicy::LeaveEmpty,
+	}
+}
+
+#[cfg(test)]
+pub(crate) fn document_with_body(body: &str, sub_elections: Vec<InputElection>) -> CandidacyContent {
+	CandidacyContent::Document{ pitch: "".into(), body: body.into(), sub_elections }
+}
+
+#[cfg(test)]
+pub(crate) fn document(sub_elections: Vec<InputElection>) -> CandidacyContent {
+	document_with_body("", sub_elections)
+}
+
+// resource allocations without fallbacks, given as (election_id, candidacy_id, weight)
+#[cfg(test)]
+pub(crate) fn allocate(voter_id: usize, allocations: Vec<(usize, usize, i64)>) -> PolityAction {
+	let resource_allocations = allocations.into_iter()
+		.map(|(election_id, candidacy_id, weight)| ResourceAllocation{ election_id, candidacy_id, weight: weight.into(), fallback_candidacy_ids: vec![] })
+		.collect();
+	PolityAction::SetAllocations{ voter_id, resource_allocations, resource_score_allocations: vec![] }
+}
+
+// person 1 puts 50 of their 100 weight behind document 10, which has won the root election by the time this returns
+#[cfg(test)]
+pub(crate) fn polity_with_document(content: CandidacyContent) -> Polity {
+	let mut polity = Polity::new(PolityState::build().with_resource().finish());
+	polity.perform(PolityAction::EnterPerson{ person_id: 1, given_weight: 100.into() }).unwrap();
+	polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content }).unwrap();
+	polity.perform(allocate(1, vec![(0, 10, 50)])).unwrap();
+	for _ in 0..2 { polity.perform(PolityAction::Recalculate).unwrap(); }
+	polity
+}
+
+// chrono has no serde support for Duration, so it's written as whole seconds plus nanoseconds, which is lossless
+#[cfg(feature = "serde")]
+mod serde_duration {
+	use serde::{Deserialize, Deserializer, Serialize, Serializer};
+	use super::Duration;
+
+	pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+		(duration.num_seconds(), duration.subsec_nanos()).serialize(serializer)
+	}
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+		let (seconds, nanoseconds) = <(i64, i32)>::deserialize(deserializer)?;
+		from_parts(seconds, nanoseconds).ok_or_else(|| serde::de::Error::custom("duration out of range"))
+	}
+
+	// subsec_nanos is negative for negative durations, while Duration::new only takes a positive nanosecond part
+	fn from_parts(seconds: i64, nanoseconds: i32) -> Option<Duration> {
+		Duration::try_seconds(seconds)?.checked_add(&Duration::nanoseconds(nanoseconds.into()))
+	}
+
+	pub mod option {
+		use super::*;
+
+		pub fn serialize<S: Serializer>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+			duration.map(|duration| (duration.num_seconds(), duration.subsec_nanos())).serialize(serializer)
+		}
+		pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+			match <Option<(i64, i32)>>::deserialize(deserializer)? {
+				Some((seconds, nanoseconds)) => from_parts(seconds, nanoseconds)
+					.map(Some)
+					.ok_or_else(|| serde::de::Error::custom("duration out of range")),
+				None => Ok(None),
+			}
+		}
+	}
+}
+
+// HashMap and HashSet iterate in a different order every run, so they're written in key order to keep the output the same every time
+// they read back as usual, so only serializing needs this
+#[cfg(feature = "serde")]
+mod serde_sorted {
+	use std::collections::{BTreeMap, HashMap, HashSet};
+	use core::borrow::Borrow;
+	use serde::{Serialize, Serializer};
+
+	pub fn map<S: Serializer, K: Ord + Serialize, V: Serialize>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error> {
+		map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+	}
+	// tables are sets of items keyed by id
+	pub fn set<S: Serializer, T: Borrow<usize> + Serialize>(set: &HashSet<T>, serializer: S) -> Result<S::Ok, S::Error> {
+		let mut items: Vec<&T> = set.iter().collect();
+		items.sort_by_key(|item| *(*item).borrow());
+		items.serialize(serializer)
+	}
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PolityActionEntry {
+	pub occurred_at: DateTime,
+	pub change: PolityAction,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-enum CandidacyContent {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CandidacyContent {
 	Office{ pitch: String },
 	Document{ pitch: String, body: String, sub_elections: Vec<InputElection> },
+	// a change to the current winner of the election, which is materialized into a full Document once it wins
+	Amendment{ pitch: String, amended_document_id: usize, patch: DocumentPatch },
 }
 
-#[derive(Debug)]
-enum PolityAction {
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DocumentPatch {
+	// applied to the amended body, in order and without overlapping
+	pub body_edits: Vec<TextEdit>,
+	pub removed_election_ids: Vec<usize>,
+	// these replace the amended sub elections with the same ids, which are carried over
+	pub changed_elections: Vec<InputElection>,
+	pub added_elections: Vec<InputElection>,
+}
+
+// replaces the characters from start up to end, counted in chars rather than bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TextEdit {
+	pub start: usize,
+	pub end: usize,
+	pub replacement: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AmendmentError {
+	NotCurrentWinner{ amended_document_id: usize },
+	UnknownElection{ election_id: usize },
+	DuplicateElection{ election_id: usize },
+	InvalidEdit{ edit_index: usize },
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PolityAction {
 	EnterPerson{ person_id: usize, given_weight: Weight },
 	SetAllocations{ voter_id: usize, resource_allocations: Vec<ResourceAllocation>, resource_score_allocations: Vec<ResourceScoreAllocation> },
-	ExitPerson{ person_id: usize },
+	// replaces all of the delegator's delegations, so an empty list revokes them
+	SetDelegations{ delegator_id: usize, delegations: Vec<Delegation> },
+	// the person's allocations, delegations and non-winning candidacies go with them, and any office they hold is left vacant
+	// winning documents can't be left without an owner, so they're handed to the successor
+	ExitPerson{ person_id: usize, successor_id: Option<usize> },
 
 	EnterCandidacy{ candidacy_id: usize, owner_id: usize, election_id: usize, content: CandidacyContent },
 	ExitCandidacy{ candidacy_id: usize },
 
 	Recalculate,
+	// only recalculates the elections whose update_frequency has passed since they last ran, as of when the action occurred
+	RecalculateDue,
 }
 
-#[derive(Debug, PartialEq)]
-enum PolityActionError {
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PolityActionError {
 	IdConflict{ id: usize, table_kind: TableKind },
 	NotFound{ id: usize, table_kind: TableKind },
 	NoCandidacy{ candidacy_id: usize, voter_id: usize },
 	NoElection{ election_id: usize, voter_id: usize },
 	NotRequiredEqualWeight{ person_id: usize, found_weight: Weight, required_equal_weight: Weight },
 	AboveAllowedWeight{ voter_id: usize, found_weight: Weight, given_weight: Weight },
+	// opposition is expressed through negative scores, a negative weight would add to the voter's budget instead of spending it
+	InvalidAllocationWeight{ voter_id: usize, election_id: usize, weight: Weight },
 	MismatchedKind{ candidacy_id: usize, expected_kind: ElectionKind },
 	MismatchedMethod{ voter_id: usize, election_id: usize, expected_method: SelectionMethodKind },
 	WinningDocumentExit{ candidacy_id: usize },
+	InvalidSeats{ candidacy_id: usize, election_id: usize, seats: usize },
+	InvalidUpdateFrequency{
+		candidacy_id: usize, election_id: usize,
+		#[cfg_attr(feature = "serde", serde(with = "serde_duration"))]
+		update_frequency: Duration,
+	},
+	// actions have to be performed in the order they occurred, though several can occur at the same time
+	OutOfOrder{ occurred_at: DateTime, latest_occurred_at: DateTime },
+	InvalidCarryOver{ candidacy_id: usize, election_id: usize },
+	InvalidAmendment{ candidacy_id: usize, error: AmendmentError },
+	IncompatibleFallback{ voter_id: usize, election_id: usize, candidacy_id: usize },
+	SelfDelegation{ person_id: usize },
+	InvalidDelegationWeight{ delegator_id: usize, delegate_id: usize, weight: Weight },
+	DelegationCycle{ delegator_id: usize, delegate_id: usize },
+	SuccessorRequired{ person_id: usize, candidacy_id: usize },
+	InvalidSuccessor{ person_id: usize, successor_id: usize },
+	// only allocations can be hypothetical in a recalculation preview
+	NotSetAllocations,
+}
+
+// inconsistencies found while recalculating, none of which stop the recalculation
+// some are expected for a while, such as allocations to a candidacy that recently exited,
+// but others can only come from state corruption, so they're reported rather than silently skipped
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IntegrityWarning {
+	TooManyWinners{ election_id: usize, seats: usize, winner_ids: Vec<usize> },
+	CandidacyWithoutElection{ candidacy_id: usize, election_id: usize },
+	AllocationToMissingElection{ voter_id: usize, election_id: usize },
+	AllocationToMissingCandidacy{ voter_id: usize, election_id: usize, candidacy_id: usize },
+	MismatchedAllocationElection{ voter_id: usize, candidacy_id: usize, allocation_election_id: usize, candidacy_election_id: usize },
+}
+
+// everything a recalculation found worth telling operators or voters about, beyond the changes themselves
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecalculationReport {
+	pub warnings: Vec<IntegrityWarning>,
+	pub fallback_moves: Vec<FallbackMove>,
+	// in id order
+	pub recalculated_election_ids: Vec<usize>,
+	pub vacancy_events: Vec<VacancyEvent>,
+}
+
+// weight a voter had stranded on a removed candidacy, moved to one of their fallbacks
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FallbackMove {
+	pub voter_id: usize,
+	pub from_candidacy_id: usize,
+	pub to_candidacy_id: usize,
+	// measured the same way as OrphanedAllocation::stranded_weight
+	pub weight: Weight,
+}
+
+// tells a voter that some of their weight no longer counts because what it was allocated to was removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OrphanedAllocation {
+	pub voter_id: usize,
+	pub election_id: usize,
+	// None when the whole election was removed, which strands the entire allocation
+	pub candidacy_id: Option<usize>,
+	pub stranded_weight: Weight,
 }
 
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum ElectionKind {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ElectionKind {
 	Document,
 	Office,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum SelectionMethodKind {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SelectionMethodKind {
 	Resource,
 	ResourceScore,
 }
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum SelectionMethod {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SelectionMethod {
 	Resource{ scale_quadratically: bool },
+	// with use_averaging each candidacy's total is the weighted average of the scores it was given rather than a sum of weights
+	// so the election's fill requirements are measured in scores too, and a Constant(3) requirement takes three recalculations
+	// to fill for a candidacy every scorer gives a score of 1, however much weight they put behind it
 	ResourceScore{ scale_quadratically: bool, use_averaging: bool },
 }
 impl SelectionMethod {
-	fn kind(&self) -> SelectionMethodKind {
+	pub fn kind(&self) -> SelectionMethodKind {
 		match self {
 			SelectionMethod::Resource{..} => SelectionMethodKind::Resource,
 			SelectionMethod::ResourceScore{..} => SelectionMethodKind::ResourceScore,
@@ -75,21 +302,36 @@ trait Allocation {
 	fn total_weight(&self) -> Weight;
 	fn compatible_method_kind() -> SelectionMethodKind;
 	fn iter_candidacies(&self) -> Vec<&usize>;
+	fn iter_fallback_candidacies(&self) -> Vec<&usize>;
+	// whether fallbacks have to be in the allocation's own election, or just in any election using the same method
+	fn fallbacks_stay_in_election() -> bool;
+	fn supported_candidacies(&self) -> Vec<usize>;
 	fn get_election_id(&self) -> usize;
+	// scales the weight by how much delegated weight the voter carries in the allocation's election
+	fn amplify(&mut self, amplification: Weight);
 }
 
-#[derive(Debug, PartialEq)]
-struct ResourceAllocation {
-	election_id: usize,
-	candidacy_id: usize,
-	weight: Weight,
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResourceAllocation {
+	pub election_id: usize,
+	pub candidacy_id: usize,
+	pub weight: Weight,
+	// tried in order if candidacy_id is removed, and may be in any other resource election
+	pub fallback_candidacy_ids: Vec<usize>,
 }
 
 impl Allocation for ResourceAllocation {
 	fn total_weight(&self) -> Weight { self.weight }
 	fn compatible_method_kind() -> SelectionMethodKind { SelectionMethodKind::Resource }
 	fn iter_candidacies(&self) -> Vec<&usize> { vec![&self.candidacy_id] }
+	fn iter_fallback_candidacies(&self) -> Vec<&usize> { self.fallback_candidacy_ids.iter().collect() }
+	fn fallbacks_stay_in_election() -> bool { false }
+	fn supported_candidacies(&self) -> Vec<usize> {
+		if self.weight > 0.into() { vec![self.candidacy_id] } else { vec![] }
+	}
 	fn get_election_id(&self) -> usize { self.election_id }
+	fn amplify(&mut self, amplification: Weight) { self.weight *= amplification; }
 }
 
 fn aggregate_resource_votes(allocations: &Vec<&ResourceAllocation>) -> HashMap<usize, Weight> {
@@ -116,50 +358,151 @@ fn aggregate_quadratic_resource_votes(allocations: &Vec<&ResourceAllocation>) ->
 }
 
 
-#[derive(Debug, PartialEq)]
-struct ResourceScoreAllocation {
-	election_id: usize,
-	approve_weight: Weight,
-	disapprove_weight: Weight,
-	scores: HashMap<usize, Weight>,
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResourceScoreAllocation {
+	pub election_id: usize,
+	pub approve_weight: Weight,
+	pub disapprove_weight: Weight,
+	#[cfg_attr(feature = "serde", serde(serialize_with = "serde_sorted::map"))]
+	pub scores: HashMap<usize, Weight>,
+	// for each scored candidacy, the candidacies in this same election its score moves to in order if it's removed
+	#[cfg_attr(feature = "serde", serde(serialize_with = "serde_sorted::map"))]
+	pub fallback_candidacy_ids: HashMap<usize, Vec<usize>>,
 }
 
 impl Allocation for ResourceScoreAllocation {
 	fn total_weight(&self) -> Weight { self.approve_weight + self.disapprove_weight }
 	fn compatible_method_kind() -> SelectionMethodKind { SelectionMethodKind::ResourceScore }
 	fn iter_candidacies(&self) -> Vec<&usize> { self.scores.keys().collect() }
+	fn iter_fallback_candidacies(&self) -> Vec<&usize> { self.fallback_candidacy_ids.values().flatten().collect() }
+	fn fallbacks_stay_in_election() -> bool { true }
+	fn supported_candidacies(&self) -> Vec<usize> {
+		if self.approve_weight <= 0.into() { return vec![]; }
+		self.scores.iter().filter(|(_, score)| **score > 0.into()).map(|(candidacy_id, _)| *candidacy_id).collect()
+	}
 	fn get_election_id(&self) -> usize { self.election_id }
+	fn amplify(&mut self, amplification: Weight) {
+		self.approve_weight *= amplification;
+		self.disapprove_weight *= amplification;
+	}
 }
 
-fn aggregate_resource_score_votes(allocations: &Vec<&ResourceScoreAllocation>) -> HashMap<usize, Weight> {
+fn aggregate_resource_score_votes(allocations: &Vec<&ResourceScoreAllocation>, use_averaging: bool) -> HashMap<usize, Weight> {
 	let mut vote_aggregation = HashMap::new();
+	let mut scorer_weights = HashMap::new();
 	for allocation in allocations {
 		let actual_approve_weight = allocation.approve_weight;
 		let actual_disapprove_weight = allocation.disapprove_weight;
 		for (candidacy_id, score) in &allocation.scores {
-			let actual_vote = score * (if *score >= 0.into() { actual_approve_weight } else { actual_disapprove_weight });
+			let actual_weight = if *score >= 0.into() { actual_approve_weight } else { actual_disapprove_weight };
+			let actual_vote = score * actual_weight;
 			vote_aggregation
 				.entry(*candidacy_id)
 				.and_modify(|t| *t += actual_vote)
 				.or_insert(actual_vote);
+			scorer_weights
+				.entry(*candidacy_id)
+				.and_modify(|t| *t += actual_weight)
+				.or_insert(actual_weight);
 		}
 	}
-	vote_aggregation
+	if use_averaging { average_by_scorer_weights(vote_aggregation, &scorer_weights) } else { vote_aggregation }
 }
-fn aggregate_quadratic_resource_score_votes(allocations: &Vec<&ResourceScoreAllocation>) -> HashMap<usize, Weight> {
+fn aggregate_quadratic_resource_score_votes(allocations: &Vec<&ResourceScoreAllocation>, use_averaging: bool) -> HashMap<usize, Weight> {
 	let mut vote_aggregation = HashMap::new();
+	let mut scorer_weights = HashMap::new();
 	for allocation in allocations {
 		let actual_approve_weight = quadratic_vote(allocation.approve_weight);
 		let actual_disapprove_weight = quadratic_vote(allocation.disapprove_weight);
 		for (candidacy_id, score) in &allocation.scores {
-			let actual_vote = score * (if *score >= 0.into() { actual_approve_weight } else { actual_disapprove_weight });
+			let actual_weight = if *score >= 0.into() { actual_approve_weight } else { actual_disapprove_weight };
+			let actual_vote = score * actual_weight;
 			vote_aggregation
 				.entry(*candidacy_id)
 				.and_modify(|t| *t += actual_vote)
 				.or_insert(actual_vote);
+			scorer_weights
+				.entry(*candidacy_id)
+				.and_modify(|t| *t += actual_weight)
+				.or_insert(actual_weight);
 		}
 	}
-	vote_aggregation
+	if use_averaging { average_by_scorer_weights(vote_aggregation, &scorer_weights) } else { vote_aggregation }
+}
+
+// reweighted range voting, so a majority can't simply take every seat of a multi-seat election
+// seats are picked one at a time by highest total, and after each pick every voter's weight is divided by
+// one plus the sum of their positive scores for the picked candidacies, relative to their own highest score
+// a picked candidacy keeps the total it had in the round it was picked, the rest keep their totals from the final round
+fn aggregate_proportional_resource_score_votes(
+	allocations: &Vec<&ResourceScoreAllocation>,
+	seats: usize,
+	scale_quadratically: bool,
+	use_averaging: bool,
+) -> HashMap<usize, Weight> {
+	let mut picked_candidacy_ids = Vec::new();
+	let mut candidacy_totals = HashMap::new();
+	loop {
+		let mut vote_aggregation = HashMap::new();
+		let mut scorer_weights = HashMap::new();
+		for allocation in allocations {
+			let maximum_score = allocation.scores.values().copied().fold(Weight::ZERO, Weight::max);
+			let picked_score: Weight = picked_candidacy_ids.iter()
+				.filter_map(|candidacy_id| allocation.scores.get(candidacy_id))
+				.map(|score| Weight::max(*score, 0.into()))
+				.sum();
+			let reweighting =
+				if maximum_score.is_zero() { Weight::ONE }
+				else { Weight::ONE / (Weight::ONE + picked_score / maximum_score) };
+
+			let (actual_approve_weight, actual_disapprove_weight) =
+				if scale_quadratically { (quadratic_vote(allocation.approve_weight), quadratic_vote(allocation.disapprove_weight)) }
+				else { (allocation.approve_weight, allocation.disapprove_weight) };
+			for (candidacy_id, score) in &allocation.scores {
+				if picked_candidacy_ids.contains(candidacy_id) { continue; }
+				let actual_weight = reweighting * if *score >= 0.into() { actual_approve_weight } else { actual_disapprove_weight };
+				let actual_vote = score * actual_weight;
+				vote_aggregation
+					.entry(*candidacy_id)
+					.and_modify(|t| *t += actual_vote)
+					.or_insert(actual_vote);
+				scorer_weights
+					.entry(*candidacy_id)
+					.and_modify(|t| *t += actual_weight)
+					.or_insert(actual_weight);
+			}
+		}
+		let vote_aggregation = if use_averaging { average_by_scorer_weights(vote_aggregation, &scorer_weights) } else { vote_aggregation };
+
+		// ties are picked by lower id, which only decides which of them is reweighted against first
+		let best = vote_aggregation.iter()
+			.filter(|(_, total_vote)| **total_vote > 0.into())
+			.max_by(|(a_id, a_total), (b_id, b_total)| a_total.cmp(b_total).then(b_id.cmp(a_id)))
+			.map(|(candidacy_id, total_vote)| (*candidacy_id, *total_vote));
+		match best {
+			Some((candidacy_id, total_vote)) if picked_candidacy_ids.len() < seats => {
+				candidacy_totals.insert(candidacy_id, total_vote);
+				picked_candidacy_ids.push(candidacy_id);
+			},
+			_ => {
+				candidacy_totals.extend(vote_aggregation);
+				return candidacy_totals;
+			},
+		}
+	}
+}
+
+// normalizes each candidacy's total by the weight of the voters who actually scored it
+// so a candidacy few voters have gotten around to isn't penalized just for having fewer scorers
+fn average_by_scorer_weights(vote_aggregation: HashMap<usize, Weight>, scorer_weights: &HashMap<usize, Weight>) -> HashMap<usize, Weight> {
+	vote_aggregation.into_iter()
+		.map(|(candidacy_id, total_vote)| {
+			let scorer_weight = *scorer_weights.get(&candidacy_id).unwrap_or(&0.into());
+			let average_vote = if scorer_weight.is_zero() { 0.into() } else { total_vote / scorer_weight };
+			(candidacy_id, average_vote)
+		})
+		.collect()
 }
 
 fn quadratic_vote(weight: Weight) -> Weight {
@@ -167,19 +510,48 @@ fn quadratic_vote(weight: Weight) -> Weight {
 }
 
 
+// hands some of the delegator's given_weight to the delegate, who then votes with it in every election the scope covers
+// delegated weight comes out of the same budget as allocations, so it can't also be allocated directly
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Delegation {
+	pub delegate_id: usize,
+	pub weight: Weight,
+	pub scope: DelegationScope,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DelegationScope {
+	Everything,
+	// every election defined by this document candidacy, and every election defined by documents winning those, and so on
+	UnderDocument(usize),
+}
+
+
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-struct InputElection {
-	id: usize,
-	title: String,
-	description: String,
-	kind: ElectionKind,
-	selection_method: SelectionMethod,
-
-	nomination_fill_method: NominationFillMethod,
-	election_fill_method: ElectionFillMethod,
-	// negative_buckets: NegativeBucketsKind,
-	// update_frequency: chrono::Duration,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InputElection {
+	pub id: usize,
+	pub title: String,
+	pub description: String,
+	pub kind: ElectionKind,
+	pub selection_method: SelectionMethod,
+	// how many winners the election has at once, document elections always have exactly one
+	pub seats: usize,
+	// when set, id refers to an election defined by the document this candidacy would replace
+	// and if this candidacy wins that election is kept along with its candidacies and allocations, with only its settings updated
+	pub carries_over: bool,
+
+	pub nomination_fill_method: NominationFillMethod,
+	pub election_fill_method: ElectionFillMethod,
+	pub negative_buckets: NegativeBucketsKind,
+	// the least time between two recalculations of the election when only due elections are recalculated
+	// None means the election is always due
+	#[cfg_attr(feature = "serde", serde(with = "serde_duration::option"))]
+	pub update_frequency: Option<Duration>,
+	pub vacancy_policy: VacancyPolicy,
 }
 
 impl InputElection {
@@ -190,8 +562,12 @@ impl InputElection {
 			description: self.description.clone(),
 			kind: self.kind,
 			selection_method: self.selection_method,
+			seats: self.seats,
 			nomination_fill_method: self.nomination_fill_method,
 			election_fill_method: self.election_fill_method,
+			negative_buckets: self.negative_buckets,
+			update_frequency: self.update_frequency,
+			vacancy_policy: self.vacancy_policy,
 			defining_document_id: Some(defining_document_id),
 		}
 	}
@@ -199,28 +575,113 @@ impl InputElection {
 
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum NominationFillMethod {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NominationFillMethod {
 	Constant(Weight),
-	// NoiseAdaptive,
+	// the base requirement plus churn_multiplier times the recent churn of the election
+	// while allocations are swinging around a candidacy has to show more sustained support to get through
+	NoiseAdaptive{ base: Weight, churn_multiplier: Weight },
 	None,
 }
 
+// tracked across recalculations for every election using NominationFillMethod::NoiseAdaptive
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ElectionChurn {
+	// the total vote of every candidacy as of the last recalculation
+	#[cfg_attr(feature = "serde", serde(serialize_with = "serde_sorted::map"))]
+	pub previous_totals: HashMap<usize, Weight>,
+	// how far those totals moved between recalculations, decaying by half every recalculation
+	pub churn: Weight,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum ElectionFillMethod {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ElectionFillMethod {
 	Constant(Weight),
-	// OnlyElectorateSize,
-	// ElectorateSizeWithWideness,
+	// this much weight for every person in the electorate
+	OnlyElectorateSize(Weight),
+	// the same as OnlyElectorateSize, but a challenger supported by a larger share of the electorate needs up to half as much
+	ElectorateSizeWithWideness(Weight),
+}
+
+// whether active opposition can push a candidacy's bucket below zero
+// with WithoutRemoval a bucket can't go below the negative fill requirement,
+// with WithRemoval a candidacy is removed once its bucket reaches the negative fill requirement
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NegativeBucketsKind {
+	None,
+	WithoutRemoval,
+	WithRemoval,
+}
+
+// what happens to a seat while the winner who held it is gone
+// with RequireFullBucketFill every challenger's bucket is emptied when the seat is vacated, so nobody takes it with a head start
+// with AppointRunnerUp the strongest challenger in the election stage takes the seat at the next recalculation, without filling a bucket
+// with LeaveEmpty the seat simply counts as having no support until a challenger fills their bucket
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VacancyPolicy {
+	RequireFullBucketFill,
+	AppointRunnerUp,
+	LeaveEmpty,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Vacancy {
+	pub vacated_candidacy_id: usize,
+	pub vacated_at: DateTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VacancyEvent {
+	Opened{ election_id: usize, vacated_candidacy_id: usize },
+	// appointed is true when the seat was filled by the AppointRunnerUp policy rather than a bucket fill
+	Filled{ election_id: usize, vacated_candidacy_id: usize, candidacy_id: usize, appointed: bool },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CandidacyRemovalReason {
+	Exited,
+	DefiningDocumentReplaced,
+	// the document an amendment was written against was replaced by something else
+	AmendedDocumentReplaced,
+	NegativeBucket,
+	OwnerExited,
+}
+
+// a document that won an election, kept after it's replaced so past constitutions can still be looked up
+// in force from enacted_at up to but not including replaced_at
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Enactment {
+	pub candidacy_id: usize,
+	pub content: CandidacyContent,
+	pub enacted_at: DateTime,
+	pub replaced_at: Option<DateTime>,
+}
+impl Enactment {
+	pub fn in_force_at(&self, at: DateTime) -> bool {
+		self.enacted_at <= at && self.replaced_at.map(|replaced_at| at < replaced_at).unwrap_or(true)
+	}
 }
 
-// #[derive(Debug)]
-// enum NegativeBucketsKind {
-// 	None,
-// 	WithoutRemoval,
-// 	WithRemoval,
-// }
+// the documents in force in an election at some moment, along with those in force in the document elections they defined
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConstitutionNode {
+	pub election_id: usize,
+	pub enactment: Enactment,
+	pub children: Vec<ConstitutionNode>,
+}
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum CandidacyStatus {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CandidacyStatus {
 	Nomination(Weight),
 	Election(Weight),
 	Winner,
@@ -231,8 +692,19 @@ fn calculate_polity_action(
 	state: &PolityState,
 	errors: &mut Vec<PolityActionError>,
 	changes: &mut Vec<PolityStateChange>,
+	report: &mut RecalculationReport,
+	occurred_at: DateTime,
 	action: PolityAction,
 ) -> Option<()> {
+	let latest_occurred_at = state.latest_occurred_at;
+	if occurred_at < latest_occurred_at {
+		errors.push(PolityActionError::OutOfOrder{ occurred_at, latest_occurred_at });
+		return None;
+	}
+	if occurred_at > latest_occurred_at {
+		changes.push(PolityStateChange::AdvanceClock{ occurred_at });
+	}
+
 	match action {
 		PolityAction::EnterPerson{ person_id, given_weight } => {
 			if let Some(required_equal_weight) = state.required_equal_weight {
@@ -247,13 +719,74 @@ fn calculate_polity_action(
 		PolityAction::SetAllocations{ voter_id, resource_allocations, resource_score_allocations } => {
 			let person = require_present(errors, &state.person_table, &voter_id)?;
 			let (resource_allocations, resource_score_allocations) =
-				validate_allocations(errors, state, &person, resource_allocations, resource_score_allocations)?;
+				validate_allocations(errors, state, person, resource_allocations, resource_score_allocations)?;
 
 			changes.push(PolityStateChange::SetResourceAllocations{ voter_id, allocations: resource_allocations });
 			changes.push(PolityStateChange::SetResourceScoreAllocations{ voter_id, allocations: resource_score_allocations });
 		},
-		PolityAction::ExitPerson{ person_id } => {
+		PolityAction::SetDelegations{ delegator_id, delegations } => {
+			let person = require_present(errors, &state.person_table, &delegator_id)?;
+			let delegations = validate_delegations(errors, state, person, delegations)?;
+
+			changes.push(PolityStateChange::SetDelegations{ delegator_id, delegations });
+		},
+		PolityAction::ExitPerson{ person_id, successor_id } => {
 			require_present(errors, &state.person_table, &person_id)?;
+			if let Some(successor_id) = successor_id {
+				require_present(errors, &state.person_table, &successor_id)?;
+				if successor_id == person_id {
+					errors.push(PolityActionError::InvalidSuccessor{ person_id, successor_id });
+					return None;
+				}
+			}
+
+			let mut owned_candidacies: Vec<_> = state.candidacy_table.iter().filter(|c| c.owner_id == person_id).collect();
+			owned_candidacies.sort_by_key(|c| c.id);
+			let is_winning_document = |candidacy: &StorageCandidacy| matches!(
+				(&candidacy.status, &candidacy.content),
+				(CandidacyStatus::Winner, CandidacyContent::Document{..})
+			);
+			for candidacy in &owned_candidacies {
+				if !is_winning_document(candidacy) { continue; }
+				match successor_id {
+					Some(owner_id) => { changes.push(PolityStateChange::SetCandidacyOwner{ candidacy_id: candidacy.id, owner_id }); },
+					None => { errors.push(PolityActionError::SuccessorRequired{ person_id, candidacy_id: candidacy.id }); },
+				}
+			}
+			if !errors.is_empty() { return None; }
+
+			let removed_candidacy_ids: Vec<_> = owned_candidacies.into_iter()
+				.filter(|candidacy| !is_winning_document(candidacy))
+				.map(|candidacy| candidacy.id)
+				.collect();
+			for candidacy_id in &removed_candidacy_ids {
+				changes.push(PolityStateChange::RemoveCandidacy{ candidacy_id: *candidacy_id, reason: CandidacyRemovalReason::OwnerExited });
+			}
+			vacate_seats(state, changes, report, occurred_at, &removed_candidacy_ids);
+
+			if state.resource_allocation_table.contains_key(&person_id) {
+				changes.push(PolityStateChange::SetResourceAllocations{ voter_id: person_id, allocations: vec![] });
+			}
+			if state.resource_score_allocation_table.contains_key(&person_id) {
+				changes.push(PolityStateChange::SetResourceScoreAllocations{ voter_id: person_id, allocations: vec![] });
+			}
+			// anyone delegating to this person gets that weight back to allocate themselves
+			let mut delegator_ids: Vec<_> = state.delegation_table.iter()
+				.filter(|(_, delegations)| delegations.iter().any(|delegation| delegation.delegate_id == person_id))
+				.map(|(delegator_id, _)| *delegator_id)
+				.collect();
+			delegator_ids.sort();
+			for delegator_id in delegator_ids {
+				let delegations = state.delegation_table[&delegator_id].iter()
+					.filter(|delegation| delegation.delegate_id != person_id)
+					.cloned()
+					.collect();
+				changes.push(PolityStateChange::SetDelegations{ delegator_id, delegations });
+			}
+			if state.delegation_table.contains_key(&person_id) {
+				changes.push(PolityStateChange::SetDelegations{ delegator_id: person_id, delegations: vec![] });
+			}
+
 			changes.push(PolityStateChange::RemovePerson{ person_id });
 		},
 
@@ -261,7 +794,7 @@ fn calculate_polity_action(
 			require_not_present(errors, &state.candidacy_table, &candidacy_id)?;
 			require_present(errors, &state.person_table, &owner_id)?;
 			let election = require_present(errors, &state.election_table, &election_id)?;
-			validate_candidacy_content(errors, &content, election.kind, candidacy_id)?;
+			validate_candidacy_content(errors, state, &content, election, candidacy_id)?;
 
 			let status = make_initial_status(election.nomination_fill_method);
 			let candidacy = StorageCandidacy{ id: candidacy_id, owner_id, election_id, content, status };
@@ -272,240 +805,905 @@ fn calculate_polity_action(
 			validate_not_winning_document(errors, &candidacy.status, &candidacy.content, candidacy_id)?;
 
 			// no need to issue election deletions, this isn't allowed to be a document winner
-			// similarly no need to delete allocations, we should just ignore allocations to non-existent candidacies
-			changes.push(PolityStateChange::RemoveCandidacy{ candidacy_id });
+			// similarly no need to delete allocations, voters are sent an OrphanedAllocation notice and can reallocate whenever they want
+			changes.push(PolityStateChange::RemoveCandidacy{ candidacy_id, reason: CandidacyRemovalReason::Exited });
+			vacate_seats(state, changes, report, occurred_at, &[candidacy_id]);
 		},
 
 		PolityAction::Recalculate => {
-			perform_polity_recalculation(state, errors, changes)?;
+			perform_polity_recalculation(state, errors, changes, report, occurred_at, false)?;
+		},
+		PolityAction::RecalculateDue => {
+			perform_polity_recalculation(state, errors, changes, report, occurred_at, true)?;
 		},
 	}
 
 	Some(())
 }
 
+// records a vacancy for every winning office among the removed candidacies
+// candidacies that are themselves being removed are left alone when buckets are emptied
+fn vacate_seats(
+	state: &PolityState,
+	changes: &mut Vec<PolityStateChange>,
+	report: &mut RecalculationReport,
+	occurred_at: DateTime,
+	removed_candidacy_ids: &[usize],
+) {
+	let mut vacated_by_election_id = BTreeMap::new();
+	for candidacy_id in removed_candidacy_ids {
+		let candidacy = match state.candidacy_table.get(candidacy_id) {
+			Some(candidacy) if candidacy.status == CandidacyStatus::Winner => candidacy,
+			_ => continue,
+		};
+		let election = match state.election_table.get(&candidacy.election_id) {
+			Some(election) if election.kind == ElectionKind::Office => election,
+			_ => continue,
+		};
+		vacated_by_election_id.entry(election.id).or_insert_with(Vec::new).push(candidacy.id);
+	}
+
+	for (election_id, vacated_candidacy_ids) in vacated_by_election_id {
+		for vacated_candidacy_id in vacated_candidacy_ids {
+			let vacancy = Vacancy{ vacated_candidacy_id, vacated_at: occurred_at };
+			changes.push(PolityStateChange::InsertVacancy{ election_id, vacancy });
+			report.vacancy_events.push(VacancyEvent::Opened{ election_id, vacated_candidacy_id });
+		}
+
+		if state.election_table.get(&election_id).map(|election| election.vacancy_policy) != Some(VacancyPolicy::RequireFullBucketFill) { continue; }
+		let mut challengers: Vec<_> = state.candidacy_table.iter()
+			.filter(|c| c.election_id == election_id && !removed_candidacy_ids.contains(&c.id))
+			.filter(|c| matches!(c.status, CandidacyStatus::Election(bucket) if bucket != Weight::ZERO))
+			.map(|c| c.id)
+			.collect();
+		challengers.sort();
+		for candidacy_id in challengers {
+			changes.push(PolityStateChange::SetCandidacyStatus{ candidacy_id, status: CandidacyStatus::Election(0.into()) });
+		}
+	}
+}
+
 fn make_initial_status(nomination_fill_method: NominationFillMethod) -> CandidacyStatus {
 	match nomination_fill_method {
 		NominationFillMethod::Constant(_) => { CandidacyStatus::Nomination(0.into()) },
+		NominationFillMethod::NoiseAdaptive{..} => { CandidacyStatus::Nomination(0.into()) },
 		NominationFillMethod::None => { CandidacyStatus::Election(0.into()) },
 	}
 }
 
-fn perform_polity_recalculation(
-	state: &PolityState,
-	errors: &mut Vec<PolityActionError>,
-	changes: &mut Vec<PolityStateChange>,
-	// integrity_warnings: &mut Vec<IntegrityWarning>,
-) -> Option<()> {
-	let mut grouped_candidacies = HashMap::new();
-	for candidacy in &state.candidacy_table {
-		grouped_candidacies
-			.entry(candidacy.election_id)
-			.and_modify(|v: &mut HashSet<&StorageCandidacy>| { v.insert(&candidacy); })
-			.or_default();
-	}
-	let grouped_candidacies = grouped_candidacies;
-
-	// find all allocations and group them by election_id
-	let mut resource_allocations_by_election_id = HashMap::new();
-	for allocation in state.resource_allocation_table.values().flatten() {
-		resource_allocations_by_election_id
-			.entry(allocation.election_id)
-			.and_modify(|v: &mut Vec<&ResourceAllocation>| { v.push(&allocation); })
-			.or_default();
-	}
-	let resource_allocations_by_election_id = resource_allocations_by_election_id;
-
-	let mut resource_score_allocations_by_election_id = HashMap::new();
-	for allocation in state.resource_score_allocation_table.values().flatten() {
-		resource_score_allocations_by_election_id
-			.entry(allocation.election_id)
-			.and_modify(|v: &mut Vec<&ResourceScoreAllocation>| { v.push(&allocation); })
-			.or_default();
-	}
-	let resource_score_allocations_by_election_id = resource_score_allocations_by_election_id;
-
-	for (election_id, candidacies) in grouped_candidacies {
-		perform_election_recalculation(
-			state, errors, changes, election_id, &candidacies,
-			&resource_allocations_by_election_id,
-			&resource_score_allocations_by_election_id,
-		);
+fn calculate_nomination_fill_requirement(nomination_fill_method: NominationFillMethod, churn: Option<&ElectionChurn>) -> Weight {
+	match nomination_fill_method {
+		NominationFillMethod::Constant(requirement) => { requirement },
+		NominationFillMethod::NoiseAdaptive{ base, churn_multiplier } => {
+			base + churn_multiplier * churn.map(|c| c.churn).unwrap_or(0.into())
+		},
+		// candidacies start in the election stage, so there's never a nomination bucket to compare against this
+		NominationFillMethod::None => { 0.into() },
 	}
+}
 
-	Some(())
+fn calculate_next_churn(previous: Option<&ElectionChurn>, current_totals: HashMap<usize, Weight>) -> ElectionChurn {
+	let empty_totals = HashMap::new();
+	let (previous_totals, previous_churn) = match previous {
+		Some(previous) => (&previous.previous_totals, previous.churn),
+		None => (&empty_totals, 0.into()),
+	};
+
+	// candidacies that appeared or disappeared count as having moved from or to zero
+	// summed in id order, since decimal sums can round differently in a different order
+	let candidacy_ids: BTreeSet<&usize> = previous_totals.keys().chain(current_totals.keys()).collect();
+	let movement: Weight = candidacy_ids.into_iter()
+		.map(|candidacy_id| {
+			let previous_total = *previous_totals.get(candidacy_id).unwrap_or(&0.into());
+			let current_total = *current_totals.get(candidacy_id).unwrap_or(&0.into());
+			(current_total - previous_total).abs()
+		})
+		.sum();
+
+	ElectionChurn { previous_totals: current_totals, churn: (previous_churn + movement) / Weight::TWO }
 }
 
-fn perform_election_recalculation(
+fn calculate_election_fill_requirement(
+	election_fill_method: ElectionFillMethod,
+	electorate_size: usize,
+	supporter_count: usize,
+) -> Weight {
+	match election_fill_method {
+		ElectionFillMethod::Constant(requirement) => { requirement },
+		ElectionFillMethod::OnlyElectorateSize(weight_per_person) => {
+			weight_per_person * Weight::from(electorate_size)
+		},
+		ElectionFillMethod::ElectorateSizeWithWideness(weight_per_person) => {
+			let requirement = weight_per_person * Weight::from(electorate_size);
+			if electorate_size == 0 { return requirement; }
+
+			// the share of the electorate supporting this challenger, which can't be more than all of them
+			let wideness = Weight::min(Weight::from(supporter_count) / Weight::from(electorate_size), 1.into());
+			requirement * (Weight::ONE - wideness / Weight::TWO)
+		},
+	}
+}
+
+fn perform_polity_recalculation(
 	state: &PolityState,
 	errors: &mut Vec<PolityActionError>,
 	changes: &mut Vec<PolityStateChange>,
-	election_id: usize,
-	candidacies: &HashSet<&StorageCandidacy>,
-	resource_allocations_by_election_id: &HashMap<usize, Vec<&ResourceAllocation>>,
-	resource_score_allocations_by_election_id: &HashMap<usize, Vec<&ResourceScoreAllocation>>,
+	report: &mut RecalculationReport,
+	occurred_at: DateTime,
+	only_due: bool,
 ) -> Option<()> {
-	// simply ignore (or mark) allocations that point to candidacies that no longer exist, since that's probably not the fault of the voter
-	// we just need to notify them to switch their weights, which they can do whenever they want
-	let election = require_present(errors, &state.election_table, &election_id)?;
-	let empty_resource_allocations = Vec::new();
-	let empty_resource_score_allocations = Vec::new();
-
-	let aggregation = match election.selection_method {
-		SelectionMethod::Resource{ scale_quadratically } => {
-			let allocations = resource_allocations_by_election_id.get(&election_id).unwrap_or(&empty_resource_allocations);
-			if !scale_quadratically { aggregate_resource_votes(allocations) }
-			else { aggregate_quadratic_resource_votes(allocations) }
-		},
-		SelectionMethod::ResourceScore{ scale_quadratically, use_averaging: _use_averaging } => {
-			let allocations = resource_score_allocations_by_election_id.get(&election_id).unwrap_or(&empty_resource_score_allocations);
-			if !scale_quadratically { aggregate_resource_score_votes(allocations) }
-			else { aggregate_quadratic_resource_score_votes(allocations) }
-		},
-	};
+	// elections are visited in id order and voters are visited in id order
+	// so the same state always produces the same changes, and weights are always summed in the same order
+	// nothing here goes through every candidacy or allocation, only the indexes of the elections being recalculated are used
 
-	let mut winner_entries = Vec::new();
-	let mut candidacy_entries = Vec::new();
-	for candidacy in candidacies {
-		let total_vote = *aggregation.get(&candidacy.id).unwrap_or(&0.into());
-		match candidacy.status {
-			CandidacyStatus::Nomination(bucket) => {
-				candidacy_entries.push(CandidacyEntry{ candidacy_id: candidacy.id, is_nomination: true, bucket, total_vote });
-			},
-			CandidacyStatus::Election(bucket) => {
-				candidacy_entries.push(CandidacyEntry{ candidacy_id: candidacy.id, is_nomination: false, bucket, total_vote });
-			},
-			CandidacyStatus::Winner => {
-				winner_entries.push((candidacy.id, total_vote));
-			},
-		}
-	}
+	// weight stranded on removed candidacies moves to the voter's fallbacks before anything is counted
+	// so the moved weight already counts in this recalculation
+	let moved_resource_allocations = apply_resource_fallbacks(state, changes, &mut report.fallback_moves);
+	let moved_resource_score_allocations = apply_resource_score_fallbacks(state, changes, &mut report.fallback_moves);
 
-	// TODO actually calculate these
-	let nomination_fill_requirement = 0.into();
-	let election_fill_requirement = 0.into();
+	check_allocation_integrity(state, &mut report.warnings, &moved_resource_allocations, &state.resource_allocation_table);
+	check_allocation_integrity(state, &mut report.warnings, &moved_resource_score_allocations, &state.resource_score_allocation_table);
 
-	// TODO issue a warning if there's more than one winner
-	let current_winner = if winner_entries.len() == 1 { Some(winner_entries[0]) } else { None };
-	let (new_winner, candidacy_new_statuses) =
-		calculate_next_statuses(nomination_fill_requirement, election_fill_requirement, current_winner, candidacy_entries);
+	let is_recalculated = |election_id: usize| {
+		state.election_table.contains(&election_id) && (!only_due || is_election_due(state, election_id, occurred_at))
+	};
+	// elections without candidacies have nothing to recalculate, so they're clean as soon as they're due
+	let mut clean_election_ids: Vec<_> = state.dirty_election_ids.iter().copied().filter(|election_id| is_recalculated(*election_id)).collect();
+	clean_election_ids.sort();
+	if !clean_election_ids.is_empty() {
+		changes.push(PolityStateChange::MarkElectionsClean{ election_ids: clean_election_ids });
+	}
 
-	// issue candidacy updates for all that changed
-	for (candidacy_id, status) in candidacy_new_statuses {
-		changes.push(PolityStateChange::SetCandidacyStatus{ candidacy_id, status });
+	// only dirty elections are actually recalculated, since any other would come out exactly as it is
+	// weight moved by fallbacks in this recalculation hasn't been seen by its new election yet
+	let mut dirty_election_ids = state.dirty_election_ids.clone();
+	for fallback_move in &report.fallback_moves {
+		dirty_election_ids.extend(state.candidacy_table.get(&fallback_move.to_candidacy_id).map(|candidacy| candidacy.election_id));
 	}
 
-	if let ElectionKind::Document = election.kind {
-		// create sub elections defined by candidacy
-		if let Some(new_winner_id) = new_winner {
-			if let Some(new_winner_document) = candidacies.get(&new_winner_id) {
-				if let CandidacyContent::Document{ sub_elections, .. } = &new_winner_document.content {
-					for sub_election in sub_elections {
-						changes.push(PolityStateChange::InsertElection{ election: sub_election.make_election(new_winner_document.id) });
-					}
+	for (&election_id, candidacy_ids) in &state.candidacy_ids_by_election_id {
+		let election = match state.election_table.get(&election_id) {
+			Some(election) => election,
+			None => {
+				for &candidacy_id in candidacy_ids {
+					report.warnings.push(IntegrityWarning::CandidacyWithoutElection{ candidacy_id, election_id });
 				}
-			}
+				continue;
+			},
+		};
+		// every election is checked, even the ones that aren't recalculated
+		if let Some(winner_ids) = state.winner_ids_by_election_id.get(&election_id).filter(|winner_ids| winner_ids.len() > usize::max(election.seats, 1)) {
+			let winner_ids = winner_ids.iter().copied().collect();
+			report.warnings.push(IntegrityWarning::TooManyWinners{ election_id, seats: election.seats, winner_ids });
 		}
+		if !is_recalculated(election_id) { continue; }
 
-		// issue election and candidacy deletions for those no longer live
-		if let Some((old_winner_id, _)) = current_winner {
-			delete_under_document(state, changes, old_winner_id);
-
-			fn delete_under_document(state: &PolityState, changes: &mut Vec<PolityStateChange>, exiting_candidacy_id: usize) {
-				changes.push(PolityStateChange::RemoveCandidacy{ candidacy_id: exiting_candidacy_id });
-
-				for election in state.election_table.iter().filter(|e| e.defining_document_id == Some(exiting_candidacy_id)) {
-					let election_id = election.id;
-					changes.push(PolityStateChange::RemoveElection{ election_id });
+		if dirty_election_ids.contains(&election_id) {
+			let candidacies = candidacy_ids.iter().filter_map(|candidacy_id| state.candidacy_table.get(candidacy_id)).collect();
+			let amplifications = calculate_delegation_amplifications(state, election_id);
+			let resource_allocations = collect_election_allocations(
+				election_id, &amplifications, &state.resource_voter_ids_by_election_id, &moved_resource_allocations, &state.resource_allocation_table,
+			);
+			let resource_score_allocations = collect_election_allocations(
+				election_id, &amplifications, &state.resource_score_voter_ids_by_election_id, &moved_resource_score_allocations, &state.resource_score_allocation_table,
+			);
 
-					for child_candidacy in state.candidacy_table.iter().filter(|c| c.election_id == election_id) {
-						delete_under_document(state, changes, child_candidacy.id);
-					}
-				}
-			}
+			let mut supporters_by_candidacy_id = HashMap::new();
+			count_supporters(&mut supporters_by_candidacy_id, &resource_allocations);
+			count_supporters(&mut supporters_by_candidacy_id, &resource_score_allocations);
+			let supporter_counts = supporters_by_candidacy_id.into_iter()
+				.map(|(candidacy_id, supporters)| (candidacy_id, supporters.len()))
+				.collect();
+
+			perform_election_recalculation(
+				state, errors, changes, report, occurred_at, election_id, &candidacies,
+				&resource_allocations.iter().map(|(_, allocation)| allocation).collect(),
+				&resource_score_allocations.iter().map(|(_, allocation)| allocation).collect(),
+				&supporter_counts,
+			);
 		}
+		report.recalculated_election_ids.push(election_id);
+		changes.push(PolityStateChange::SetLastRecalculated{ election_id, recalculated_at: occurred_at });
 	}
 
 	Some(())
 }
 
+// every allocation in the election along with its voter, in voter id order
+// with fallback moves from this recalculation applied and the weight delegated to the voter added in
+fn collect_election_allocations<A: Allocation + Clone>(
+	election_id: usize,
+	amplifications: &HashMap<usize, Weight>,
+	voter_ids_by_election_id: &BTreeMap<usize, BTreeSet<usize>>,
+	moved_allocations: &BTreeMap<usize, Vec<A>>,
+	allocation_table: &HashMap<usize, Vec<A>>,
+) -> Vec<(usize, A)> {
+	let mut voter_ids = voter_ids_by_election_id.get(&election_id).cloned().unwrap_or_default();
+	for (voter_id, allocations) in moved_allocations {
+		if allocations.iter().any(|allocation| allocation.get_election_id() == election_id) {
+			voter_ids.insert(*voter_id);
+		}
+	}
 
-#[derive(Debug)]
-struct CandidacyEntry {
-	candidacy_id: usize,
-	is_nomination: bool,
-	bucket: Weight,
-	total_vote: Weight,
+	let mut election_allocations = Vec::new();
+	for voter_id in voter_ids {
+		let amplification = *amplifications.get(&voter_id).unwrap_or(&Weight::ONE);
+		for allocation in current_allocations(moved_allocations, allocation_table, voter_id) {
+			if allocation.get_election_id() != election_id { continue; }
+			let mut allocation = allocation.clone();
+			allocation.amplify(amplification);
+			election_allocations.push((voter_id, allocation));
+		}
+	}
+	election_allocations
 }
 
-fn calculate_next_statuses(
-	nomination_fill_requirement: Weight,
-	election_fill_requirement: Weight,
-	current_winner: Option<(usize, Weight)>,
-	candidacy_entries: Vec<CandidacyEntry>,
-) -> (Option<usize>, HashMap<usize, CandidacyStatus>) {
+// a voter's allocations as they stand once this recalculation's fallback moves are applied
+fn current_allocations<'t, A>(moved_allocations: &'t BTreeMap<usize, Vec<A>>, allocation_table: &'t HashMap<usize, Vec<A>>, voter_id: usize) -> &'t [A] {
+	moved_allocations.get(&voter_id).or_else(|| allocation_table.get(&voter_id)).map(Vec::as_slice).unwrap_or_default()
+}
 
-	let (current_winner_id, current_winner_total_vote) = current_winner.unwrap_or((0, 0.into()));
-	let current_winner_id = if current_winner_id == 0 { None } else { Some(current_winner_id) };
-	let mut candidacy_new_statuses = HashMap::new();
+// the elections of every allocation that was added, removed or changed
+fn changed_allocation_election_ids<A: Allocation + PartialEq>(old_allocations: &[A], new_allocations: &[A]) -> Vec<usize> {
+	let removed = old_allocations.iter().filter(|allocation| !new_allocations.contains(allocation));
+	let added = new_allocations.iter().filter(|allocation| !old_allocations.contains(allocation));
+	removed.chain(added).map(|allocation| allocation.get_election_id()).collect()
+}
 
-	let mut positive_filled_maximum = 0.into();
-	let mut current_possible_winners = Vec::new();
-	for CandidacyEntry{candidacy_id, is_nomination, bucket, total_vote} in candidacy_entries {
-		if is_nomination {
-			let candidacy_new_bucket = Weight::max(
-				bucket + total_vote,
-				0.into(),
-			);
-			let new_status =
-				if candidacy_new_bucket >= nomination_fill_requirement { CandidacyStatus::Election(0.into()) }
-				else { CandidacyStatus::Nomination(candidacy_new_bucket) };
+fn is_election_due(state: &PolityState, election_id: usize, occurred_at: DateTime) -> bool {
+	let update_frequency = state.election_table.get(&election_id).and_then(|election| election.update_frequency);
+	match (update_frequency, state.last_recalculated_table.get(&election_id)) {
+		(Some(update_frequency), Some(last_recalculated_at)) => last_recalculated_at.checked_add_signed(update_frequency)
+			.map(|due_at| occurred_at >= due_at)
+			.unwrap_or(false),
+		_ => true,
+	}
+}
 
-			candidacy_new_statuses.insert(candidacy_id, new_status);
+// a resource allocation falls back to the first of its fallbacks that still exists in any resource election
+// and whatever fallbacks came after that one are kept in case it disappears too
+// only voters with stale allocations can have anything to move, and only the voters whose allocations moved are returned
+fn apply_resource_fallbacks(
+	state: &PolityState,
+	changes: &mut Vec<PolityStateChange>,
+	fallback_moves: &mut Vec<FallbackMove>,
+) -> BTreeMap<usize, Vec<ResourceAllocation>> {
+	let mut moved_allocations = BTreeMap::new();
+	for &voter_id in &state.stale_allocation_voter_ids {
+		let mut allocations = match state.resource_allocation_table.get(&voter_id) {
+			Some(allocations) => allocations.clone(),
+			None => continue,
+		};
+		let mut moved = false;
+		for allocation in allocations.iter_mut() {
+			if state.candidacy_table.contains(&allocation.candidacy_id) { continue; }
+
+			let fallback = allocation.fallback_candidacy_ids.iter().enumerate().find_map(|(position, candidacy_id)| {
+				let candidacy = state.candidacy_table.get(candidacy_id)?;
+				let election = state.election_table.get(&candidacy.election_id)?;
+				if election.selection_method.kind() != SelectionMethodKind::Resource { return None; }
+				Some((position, candidacy))
+			});
+			let (position, candidacy) = match fallback {
+				Some(fallback) => fallback,
+				None => continue,
+			};
+
+			fallback_moves.push(FallbackMove{
+				voter_id, from_candidacy_id: allocation.candidacy_id, to_candidacy_id: candidacy.id, weight: allocation.weight,
+			});
+			allocation.election_id = candidacy.election_id;
+			allocation.candidacy_id = candidacy.id;
+			allocation.fallback_candidacy_ids.drain(..=position);
+			moved = true;
 		}
-		else {
-			// TODO consider allowing buckets to *go negative* if total_vote is negative, and even possibly *removing* a candidate if they reach *negative* fill_requirement
-			let candidacy_new_bucket = Weight::max(
-				bucket + (total_vote - current_winner_total_vote),
-				0.into(),
-			);
-			candidacy_new_statuses.insert(candidacy_id, CandidacyStatus::Election(candidacy_new_bucket));
-			// it isn't sound to declare the mere highest candidate the new winner when there isn't a current winner
-			// doing so would be vulnerable, where a highly approved current winner resigns, allowing a weak challenger to immediately take the stabilized spot
-			// it makes sense to *always* require a bucket fill even in situations where there isn't a current winner
-			// the alternative would be to simply change fill_requirement to 0 if there isn't a current winner
 
-			// if this candidacy has reached the requirement then it has the chance to be the *unique* winner
-			if total_vote <= 0.into() || candidacy_new_bucket < election_fill_requirement { continue; }
+		if moved {
+			changes.push(PolityStateChange::SetResourceAllocations{ voter_id, allocations: allocations.clone() });
+			moved_allocations.insert(voter_id, allocations);
+		}
+	}
+	moved_allocations
+}
 
-			if total_vote == positive_filled_maximum {
-				current_possible_winners.push(candidacy_id);
-			}
-			else if total_vote > positive_filled_maximum {
-				positive_filled_maximum = total_vote;
-				current_possible_winners.clear();
-				current_possible_winners.push(candidacy_id);
+// a score moves to the first of its fallbacks that still exists in the same election and hasn't already been scored
+fn apply_resource_score_fallbacks(
+	state: &PolityState,
+	changes: &mut Vec<PolityStateChange>,
+	fallback_moves: &mut Vec<FallbackMove>,
+) -> BTreeMap<usize, Vec<ResourceScoreAllocation>> {
+	let mut moved_allocations = BTreeMap::new();
+	for &voter_id in &state.stale_allocation_voter_ids {
+		let mut allocations = match state.resource_score_allocation_table.get(&voter_id) {
+			Some(allocations) => allocations.clone(),
+			None => continue,
+		};
+		let mut moved = false;
+		for allocation in allocations.iter_mut() {
+			let mut scored_candidacy_ids: Vec<_> = allocation.scores.keys().copied().collect();
+			scored_candidacy_ids.sort();
+			for from_candidacy_id in scored_candidacy_ids {
+				if state.candidacy_table.contains(&from_candidacy_id) { continue; }
+
+				let position = allocation.fallback_candidacy_ids.get(&from_candidacy_id).and_then(|fallback_candidacy_ids| {
+					fallback_candidacy_ids.iter().position(|candidacy_id| {
+						!allocation.scores.contains_key(candidacy_id)
+						&& state.candidacy_table.get(candidacy_id).map(|c| c.election_id == allocation.election_id).unwrap_or(false)
+					})
+				});
+				let position = match position {
+					Some(position) => position,
+					None => continue,
+				};
+
+				let mut remaining_fallbacks = allocation.fallback_candidacy_ids.remove(&from_candidacy_id).unwrap();
+				let to_candidacy_id = remaining_fallbacks[position];
+				remaining_fallbacks.drain(..=position);
+				let score = allocation.scores.remove(&from_candidacy_id).unwrap();
+				let actual_weight = if score >= 0.into() { allocation.approve_weight } else { allocation.disapprove_weight };
+
+				fallback_moves.push(FallbackMove{ voter_id, from_candidacy_id, to_candidacy_id, weight: score.abs() * actual_weight });
+				allocation.scores.insert(to_candidacy_id, score);
+				if !remaining_fallbacks.is_empty() {
+					allocation.fallback_candidacy_ids.insert(to_candidacy_id, remaining_fallbacks);
+				}
+				moved = true;
 			}
 		}
-	}
 
-	let new_winner =
-		// there's a new unique winner
-		if current_possible_winners.len() == 1 {
-			let new_winner_id = current_possible_winners[0];
-			candidacy_new_statuses.insert(new_winner_id, CandidacyStatus::Winner);
-			Some(new_winner_id)
+		if moved {
+			changes.push(PolityStateChange::SetResourceScoreAllocations{ voter_id, allocations: allocations.clone() });
+			moved_allocations.insert(voter_id, allocations);
 		}
-		// there's a tie or no one met the requirements
-		else {
-			// the current winner (if there is one) remains the current winner
-			if let Some(winner_id) = current_winner_id {
-				candidacy_new_statuses.insert(winner_id, CandidacyStatus::Winner);
+	}
+	moved_allocations
+}
+
+// how much every unit of each delegate's own weight counts in this election once the weight delegated to them is added
+// a delegate passes on weight delegated to them in the same proportion as their own, so chains resolve all the way through
+// voters who aren't anywhere in a delegation chain are left out, their weight counts as is
+fn calculate_delegation_amplifications(state: &PolityState, election_id: usize) -> HashMap<usize, Weight> {
+	let document_ids = defining_document_ids(state, election_id);
+
+	let mut incoming_by_delegate_id: HashMap<usize, Vec<(usize, Weight)>> = HashMap::new();
+	let mut delegator_ids: Vec<_> = state.delegation_table.keys().collect();
+	delegator_ids.sort();
+	for delegator_id in delegator_ids {
+		if !state.person_table.contains(delegator_id) { continue; }
+		for Delegation{ delegate_id, weight, scope } in &state.delegation_table[delegator_id] {
+			let covers = match scope {
+				DelegationScope::Everything => true,
+				DelegationScope::UnderDocument(document_id) => document_ids.contains(document_id),
+			};
+			if covers && state.person_table.contains(delegate_id) {
+				incoming_by_delegate_id.entry(*delegate_id).or_default().push((*delegator_id, *weight));
 			}
-			None
-		};
+		}
+	}
 
-	(new_winner, candidacy_new_statuses)
+	let mut amplifications = HashMap::new();
+	let mut delegate_ids: Vec<_> = incoming_by_delegate_id.keys().copied().collect();
+	delegate_ids.sort();
+	for delegate_id in delegate_ids {
+		resolve_amplification(state, &incoming_by_delegate_id, &mut amplifications, delegate_id);
+	}
+	amplifications
+}
+
+fn resolve_amplification(
+	state: &PolityState,
+	incoming_by_delegate_id: &HashMap<usize, Vec<(usize, Weight)>>,
+	amplifications: &mut HashMap<usize, Weight>,
+	person_id: usize,
+) -> Weight {
+	if let Some(amplification) = amplifications.get(&person_id) { return *amplification; }
+	// cycles are rejected when delegations are set, but this keeps a corrupted state from recursing forever
+	amplifications.insert(person_id, Weight::ONE);
+
+	let given_weight = state.person_table.get(&person_id).map(|p| p.given_weight).unwrap_or(0.into());
+	let mut amplification = Weight::ONE;
+	if given_weight > 0.into() {
+		for (delegator_id, weight) in incoming_by_delegate_id.get(&person_id).into_iter().flatten() {
+			let delegator_amplification = resolve_amplification(state, incoming_by_delegate_id, amplifications, *delegator_id);
+			amplification += weight * delegator_amplification / given_weight;
+		}
+	}
+	amplifications.insert(person_id, amplification);
+	amplification
+}
+
+// the document that defines this election, the document that defines the election that document is in, and so on up to the root
+fn defining_document_ids(state: &PolityState, election_id: usize) -> HashSet<usize> {
+	let mut document_ids = HashSet::new();
+	let mut current_election = state.election_table.get(&election_id);
+	while let Some(document_id) = current_election.and_then(|election| election.defining_document_id) {
+		if !document_ids.insert(document_id) { break; }
+		current_election = state.candidacy_table.get(&document_id).and_then(|document| state.election_table.get(&document.election_id));
+	}
+	document_ids
+}
+
+// allocations are only validated when they're set, and candidacies and elections can disappear afterwards
+// any allocation that could have gone stale belongs to a voter in the stale set, so only those voters are checked
+fn check_allocation_integrity<A: Allocation>(
+	state: &PolityState,
+	warnings: &mut Vec<IntegrityWarning>,
+	moved_allocations: &BTreeMap<usize, Vec<A>>,
+	allocation_table: &HashMap<usize, Vec<A>>,
+) {
+	for &voter_id in &state.stale_allocation_voter_ids {
+		for allocation in current_allocations(moved_allocations, allocation_table, voter_id) {
+			let allocation_election_id = allocation.get_election_id();
+			if !state.election_table.contains(&allocation_election_id) {
+				warnings.push(IntegrityWarning::AllocationToMissingElection{ voter_id, election_id: allocation_election_id });
+				continue;
+			}
+
+			let mut candidacy_ids = allocation.iter_candidacies();
+			candidacy_ids.sort();
+			for candidacy_id in candidacy_ids {
+				let candidacy_id = *candidacy_id;
+				match state.candidacy_table.get(&candidacy_id) {
+					None => {
+						warnings.push(IntegrityWarning::AllocationToMissingCandidacy{ voter_id, election_id: allocation_election_id, candidacy_id });
+					},
+					Some(candidacy) if candidacy.election_id != allocation_election_id => {
+						warnings.push(IntegrityWarning::MismatchedAllocationElection{
+							voter_id, candidacy_id, allocation_election_id, candidacy_election_id: candidacy.election_id,
+						});
+					},
+					Some(_) => {},
+				}
+			}
+		}
+	}
+}
+
+// finds every allocation that the given changes leave pointing at a removed candidacy or election
+// this works from the changes rather than the actions, so cascaded removals are covered no matter where they came from
+// only allocations that were live in the given state are reported, so nothing is reported twice
+fn calculate_orphaned_allocations(state: &PolityState, changes: &[PolityStateChange]) -> Vec<OrphanedAllocation> {
+	let mut removed_election_ids = HashSet::new();
+	let mut removed_candidacy_ids = HashSet::new();
+	// people who are leaving don't need to be told to reallocate
+	let mut removed_person_ids = HashSet::new();
+	for change in changes {
+		match change {
+			PolityStateChange::RemovePerson{ person_id } => {
+				removed_person_ids.insert(*person_id);
+			},
+			PolityStateChange::RemoveElection{ election_id } if state.election_table.contains(election_id) => {
+				removed_election_ids.insert(*election_id);
+			},
+			PolityStateChange::RemoveCandidacy{ candidacy_id, .. } if state.candidacy_table.contains(candidacy_id) => {
+				removed_candidacy_ids.insert(*candidacy_id);
+			},
+			_ => {},
+		}
+	}
+	if removed_election_ids.is_empty() && removed_candidacy_ids.is_empty() { return Vec::new(); }
+
+	let mut notices = Vec::new();
+	for (voter_id, allocations) in &state.resource_allocation_table {
+		if removed_person_ids.contains(voter_id) { continue; }
+		// a voter can have several resource allocations in a removed election, which are reported together
+		let mut stranded_by_election_id = BTreeMap::new();
+		for allocation in allocations {
+			// allocations to candidacies that were already gone were reported when they were removed
+			if !state.candidacy_table.contains(&allocation.candidacy_id) { continue; }
+
+			if removed_election_ids.contains(&allocation.election_id) {
+				*stranded_by_election_id.entry(allocation.election_id).or_insert(Weight::ZERO) += allocation.weight;
+			}
+			else if removed_candidacy_ids.contains(&allocation.candidacy_id) {
+				notices.push(OrphanedAllocation{
+					voter_id: *voter_id, election_id: allocation.election_id, candidacy_id: Some(allocation.candidacy_id), stranded_weight: allocation.weight,
+				});
+			}
+		}
+		for (election_id, stranded_weight) in stranded_by_election_id {
+			notices.push(OrphanedAllocation{ voter_id: *voter_id, election_id, candidacy_id: None, stranded_weight });
+		}
+	}
+	for (voter_id, allocations) in &state.resource_score_allocation_table {
+		if removed_person_ids.contains(voter_id) { continue; }
+		for allocation in allocations {
+			if removed_election_ids.contains(&allocation.election_id) {
+				// scores for candidacies that were already gone were reported when they were removed
+				// so only the weights still behind a live score are stranded now
+				let live_scores: Vec<_> = allocation.scores.iter()
+					.filter(|(candidacy_id, _)| state.candidacy_table.contains(*candidacy_id))
+					.map(|(_, score)| *score)
+					.collect();
+				let mut stranded_weight = Weight::ZERO;
+				if live_scores.iter().any(|score| *score > 0.into()) { stranded_weight += allocation.approve_weight; }
+				if live_scores.iter().any(|score| *score < 0.into()) { stranded_weight += allocation.disapprove_weight; }
+				if stranded_weight > 0.into() {
+					notices.push(OrphanedAllocation{ voter_id: *voter_id, election_id: allocation.election_id, candidacy_id: None, stranded_weight });
+				}
+				continue;
+			}
+			// scores don't split the voter's weight, so what's stranded is the weight of the vote that no longer counts
+			for (candidacy_id, score) in &allocation.scores {
+				if !removed_candidacy_ids.contains(candidacy_id) { continue; }
+				let actual_weight = if *score >= 0.into() { allocation.approve_weight } else { allocation.disapprove_weight };
+				notices.push(OrphanedAllocation{
+					voter_id: *voter_id, election_id: allocation.election_id, candidacy_id: Some(*candidacy_id), stranded_weight: score.abs() * actual_weight,
+				});
+			}
+		}
+	}
+
+	notices.sort_by_key(|notice| (notice.voter_id, notice.election_id, notice.candidacy_id));
+	notices
+}
+
+// how many distinct voters positively support each candidacy
+fn count_supporters<A: Allocation>(
+	supporters_by_candidacy_id: &mut HashMap<usize, HashSet<usize>>,
+	allocations: &[(usize, A)],
+) {
+	for (voter_id, allocation) in allocations {
+		for candidacy_id in allocation.supported_candidacies() {
+			supporters_by_candidacy_id.entry(candidacy_id).or_default().insert(*voter_id);
+		}
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
+fn perform_election_recalculation(
+	state: &PolityState,
+	errors: &mut Vec<PolityActionError>,
+	changes: &mut Vec<PolityStateChange>,
+	report: &mut RecalculationReport,
+	occurred_at: DateTime,
+	election_id: usize,
+	candidacies: &HashSet<&StorageCandidacy>,
+	resource_allocations: &Vec<&ResourceAllocation>,
+	resource_score_allocations: &Vec<&ResourceScoreAllocation>,
+	supporter_counts: &HashMap<usize, usize>,
+) -> Option<()> {
+	// simply ignore allocations that point to candidacies that no longer exist, since that's probably not the fault of the voter
+	// they were sent an OrphanedAllocation notice when the candidacy was removed, and can switch their weights whenever they want
+	let election = require_present(errors, &state.election_table, &election_id)?;
+
+	let aggregation = match election.selection_method {
+		SelectionMethod::Resource{ scale_quadratically } => {
+			if !scale_quadratically { aggregate_resource_votes(resource_allocations) }
+			else { aggregate_quadratic_resource_votes(resource_allocations) }
+		},
+		SelectionMethod::ResourceScore{ scale_quadratically, use_averaging } => {
+			if election.seats > 1 { aggregate_proportional_resource_score_votes(resource_score_allocations, election.seats, scale_quadratically, use_averaging) }
+			else if !scale_quadratically { aggregate_resource_score_votes(resource_score_allocations, use_averaging) }
+			else { aggregate_quadratic_resource_score_votes(resource_score_allocations, use_averaging) }
+		},
+	};
+
+	let electorate_size = state.person_table.len();
+	let churn = match election.nomination_fill_method {
+		NominationFillMethod::NoiseAdaptive{..} => {
+			let current_totals = candidacies.iter()
+				.map(|candidacy| (candidacy.id, *aggregation.get(&candidacy.id).unwrap_or(&0.into())))
+				.collect();
+			let churn = calculate_next_churn(state.election_churn_table.get(&election_id), current_totals);
+			changes.push(PolityStateChange::SetElectionChurn{ election_id, churn: churn.clone() });
+			Some(churn)
+		},
+		_ => None,
+	};
+	let nomination_fill_requirement = calculate_nomination_fill_requirement(election.nomination_fill_method, churn.as_ref());
+
+	let mut winner_entries = Vec::new();
+	let mut candidacy_entries = Vec::new();
+	for candidacy in candidacies {
+		let total_vote = *aggregation.get(&candidacy.id).unwrap_or(&0.into());
+		match candidacy.status {
+			CandidacyStatus::Nomination(bucket) => {
+				let fill_requirement = nomination_fill_requirement;
+				candidacy_entries.push(CandidacyEntry{ candidacy_id: candidacy.id, is_nomination: true, bucket, total_vote, fill_requirement });
+			},
+			CandidacyStatus::Election(bucket) => {
+				let supporter_count = *supporter_counts.get(&candidacy.id).unwrap_or(&0);
+				let fill_requirement = calculate_election_fill_requirement(election.election_fill_method, electorate_size, supporter_count);
+				candidacy_entries.push(CandidacyEntry{ candidacy_id: candidacy.id, is_nomination: false, bucket, total_vote, fill_requirement });
+			},
+			CandidacyStatus::Winner => {
+				winner_entries.push((candidacy.id, total_vote));
+			},
+		}
+	}
+
+	winner_entries.sort_by_key(|(candidacy_id, _)| *candidacy_id);
+
+	// appointed runners up are simply incumbents from here on
+	let vacancies = state.vacancy_table.get(&election_id).map(|vacancies| vacancies.as_slice()).unwrap_or(&[]);
+	let mut appointed_ids = Vec::new();
+	if election.vacancy_policy == VacancyPolicy::AppointRunnerUp && !vacancies.is_empty() {
+		let open_seats = usize::min(vacancies.len(), election.seats.saturating_sub(winner_entries.len()));
+		appointed_ids = choose_runners_up(&candidacy_entries, open_seats);
+		candidacy_entries.retain(|entry| {
+			if !appointed_ids.contains(&entry.candidacy_id) { return true; }
+			winner_entries.push((entry.candidacy_id, entry.total_vote));
+			false
+		});
+	}
+
+	let NextStatuses{ new_winners, displaced_winners, filled_empty_seat_ids, statuses, removed_candidacy_ids } =
+		calculate_next_statuses(election.seats, election.negative_buckets, winner_entries, candidacy_entries);
+
+	// vacancies are filled oldest first, by appointments before anyone who filled their bucket
+	let filling_ids = appointed_ids.iter().map(|candidacy_id| (*candidacy_id, true))
+		.chain(filled_empty_seat_ids.iter().map(|candidacy_id| (*candidacy_id, false)));
+	for (vacancy, (candidacy_id, appointed)) in vacancies.iter().zip(filling_ids) {
+		let vacated_candidacy_id = vacancy.vacated_candidacy_id;
+		changes.push(PolityStateChange::RemoveVacancy{ election_id, vacated_candidacy_id });
+		report.vacancy_events.push(VacancyEvent::Filled{ election_id, vacated_candidacy_id, candidacy_id, appointed });
+	}
+
+	// issue candidacy updates for all that changed
+	let mut candidacy_new_statuses: Vec<_> = statuses.into_iter().collect();
+	candidacy_new_statuses.sort_by_key(|(candidacy_id, _)| *candidacy_id);
+	for (candidacy_id, status) in candidacy_new_statuses {
+		changes.push(PolityStateChange::SetCandidacyStatus{ candidacy_id, status });
+	}
+	for candidacy_id in removed_candidacy_ids {
+		changes.push(PolityStateChange::RemoveCandidacy{ candidacy_id, reason: CandidacyRemovalReason::NegativeBucket });
+	}
+
+	if let ElectionKind::Document = election.kind {
+		// find the sub elections defined by candidacy
+		// the winner being carried over from could have changed since the candidacy was entered,
+		// in which case the sub election is created fresh like any other
+		let mut carried_over_election_ids = HashSet::new();
+		let mut new_elections = Vec::new();
+		let mut new_enactments = Vec::new();
+		for new_winner_id in &new_winners {
+			if let Some(new_winner_document) = candidacies.get(new_winner_id) {
+				// a winning amendment is replaced by the full document it describes
+				let mut content = &new_winner_document.content;
+				let materialized_content;
+				if let CandidacyContent::Amendment{ pitch, amended_document_id, patch } = content {
+					let amended_content = state.candidacy_table.get(amended_document_id).map(|amended| &amended.content);
+					if let Some(materialized) = amended_content.and_then(|amended_content| materialize_amendment(amended_content, pitch, patch)) {
+						materialized_content = materialized;
+						content = &materialized_content;
+						changes.push(PolityStateChange::SetCandidacyContent{ candidacy_id: *new_winner_id, content: content.clone() });
+					}
+				}
+
+				if let CandidacyContent::Document{ sub_elections, .. } = content {
+					for sub_election in sub_elections {
+						let carried_over = sub_election.carries_over && can_carry_over(state, sub_election, &displaced_winners);
+						if carried_over { carried_over_election_ids.insert(sub_election.id); }
+						new_elections.push((sub_election.make_election(new_winner_document.id), carried_over));
+					}
+				}
+
+				let enactment = Enactment{ candidacy_id: *new_winner_id, content: content.clone(), enacted_at: occurred_at, replaced_at: None };
+				new_enactments.push(enactment);
+			}
+		}
+
+		// issue election and candidacy deletions for those no longer live
+		// an old winner is only replaced if a new winner actually took its seat
+		// deletions go first so a sub election that couldn't be carried over can still reuse its id
+		for old_winner_id in displaced_winners {
+			// amendments written against the old winner no longer describe anything, unless one of them is what replaced it
+			// amendments deeper down are in elections being removed anyway
+			let mut amendment_ids: Vec<_> = candidacies.iter()
+				.filter(|c| matches!(c.content, CandidacyContent::Amendment{ amended_document_id, .. } if amended_document_id == old_winner_id))
+				.filter(|c| !new_winners.contains(&c.id))
+				.map(|c| c.id)
+				.collect();
+			amendment_ids.sort();
+			for candidacy_id in amendment_ids {
+				changes.push(PolityStateChange::RemoveCandidacy{ candidacy_id, reason: CandidacyRemovalReason::AmendedDocumentReplaced });
+			}
+
+			changes.push(PolityStateChange::EndEnactment{ election_id, replaced_at: occurred_at });
+			delete_under_document(state, changes, &carried_over_election_ids, occurred_at, old_winner_id);
+		}
+		for enactment in new_enactments {
+			changes.push(PolityStateChange::RecordEnactment{ election_id, enactment });
+		}
+		for (election, carried_over) in new_elections {
+			if carried_over { changes.push(PolityStateChange::ReplaceElection{ election }); }
+			else { changes.push(PolityStateChange::InsertElection{ election }); }
+		}
+
+		fn delete_under_document(
+			state: &PolityState,
+			changes: &mut Vec<PolityStateChange>,
+			carried_over_election_ids: &HashSet<usize>,
+			occurred_at: DateTime,
+			exiting_candidacy_id: usize,
+		) {
+			let reason = CandidacyRemovalReason::DefiningDocumentReplaced;
+			changes.push(PolityStateChange::RemoveCandidacy{ candidacy_id: exiting_candidacy_id, reason });
+
+			// elections are removed in id order so the same state always produces the same changes
+			for &election_id in state.election_ids_by_defining_document_id.get(&exiting_candidacy_id).into_iter().flatten() {
+				if carried_over_election_ids.contains(&election_id) { continue; }
+				let election = match state.election_table.get(&election_id) {
+					Some(election) => election,
+					None => continue,
+				};
+				if election.kind == ElectionKind::Document {
+					changes.push(PolityStateChange::EndEnactment{ election_id, replaced_at: occurred_at });
+				}
+				changes.push(PolityStateChange::RemoveElection{ election_id });
+
+				for &child_candidacy_id in state.candidacy_ids_by_election_id.get(&election_id).into_iter().flatten() {
+					delete_under_document(state, changes, carried_over_election_ids, occurred_at, child_candidacy_id);
+				}
+			}
+		}
+	}
+
+	Some(())
+}
+
+
+#[derive(Debug)]
+struct CandidacyEntry {
+	candidacy_id: usize,
+	is_nomination: bool,
+	bucket: Weight,
+	total_vote: Weight,
+	// the requirement for whichever stage this candidacy is in
+	fill_requirement: Weight,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct NextStatuses {
+	// in the order they took their seats
+	new_winners: Vec<usize>,
+	// incumbents who lost their seat to one of the new winners, in id order
+	displaced_winners: Vec<usize>,
+	// new winners who took a seat nobody held, in the order they took them
+	filled_empty_seat_ids: Vec<usize>,
+	statuses: HashMap<usize, CandidacyStatus>,
+	// removed candidacies are in id order and never appear in statuses
+	removed_candidacy_ids: Vec<usize>,
+}
+
+fn calculate_next_statuses(
+	seats: usize,
+	negative_buckets: NegativeBucketsKind,
+	current_winners: Vec<(usize, Weight)>,
+	candidacy_entries: Vec<CandidacyEntry>,
+) -> NextStatuses {
+	let mut next = NextStatuses::default();
+	let allows_negative = negative_buckets != NegativeBucketsKind::None;
+
+	// every seat is held by an incumbent or empty, and an empty seat counts as an incumbent with no support
+	// challengers are always measured against the weakest seat, since that's the one they'd take
+	let mut held_seats: Vec<(Option<usize>, Weight)> = current_winners.iter()
+		.map(|(winner_id, total_vote)| (Some(*winner_id), *total_vote))
+		.collect();
+	while held_seats.len() < usize::max(seats, 1) {
+		held_seats.push((None, 0.into()));
+	}
+	sort_weakest_first(&mut held_seats);
+	let weakest_total_vote = held_seats[0].1;
+
+	let mut filled_challengers = Vec::new();
+	for CandidacyEntry{candidacy_id, is_nomination, bucket, total_vote, fill_requirement} in candidacy_entries {
+		if is_nomination {
+			let candidacy_new_bucket =
+				if allows_negative { bucket + total_vote }
+				else { Weight::max(bucket + total_vote, 0.into()) };
+			let candidacy_new_bucket = match apply_negative_bucket_limit(negative_buckets, candidacy_new_bucket, fill_requirement) {
+				Some(candidacy_new_bucket) => candidacy_new_bucket,
+				None => { next.removed_candidacy_ids.push(candidacy_id); continue; },
+			};
+
+			let new_status =
+				if candidacy_new_bucket >= fill_requirement { CandidacyStatus::Election(0.into()) }
+				else { CandidacyStatus::Nomination(candidacy_new_bucket) };
+
+			next.statuses.insert(candidacy_id, new_status);
+		}
+		else {
+			// when buckets can go negative, active opposition drains the bucket below zero
+			// and while it's below zero only net support refills it, rather than support above the weakest seat
+			let candidacy_new_bucket =
+				if allows_negative && (total_vote < 0.into() || bucket < 0.into()) { bucket + total_vote }
+				else { Weight::max(bucket + (total_vote - weakest_total_vote), 0.into()) };
+			let candidacy_new_bucket = match apply_negative_bucket_limit(negative_buckets, candidacy_new_bucket, fill_requirement) {
+				Some(candidacy_new_bucket) => candidacy_new_bucket,
+				None => { next.removed_candidacy_ids.push(candidacy_id); continue; },
+			};
+
+			next.statuses.insert(candidacy_id, CandidacyStatus::Election(candidacy_new_bucket));
+			// it isn't sound to declare the mere highest candidate the new winner when there isn't a current winner
+			// doing so would be vulnerable, where a highly approved current winner resigns, allowing a weak challenger to immediately take the stabilized spot
+			// it makes sense to *always* require a bucket fill even in situations where there isn't a current winner
+			// the alternative would be to simply change fill_requirement to 0 if there isn't a current winner
+			// how a seat left by a resigning winner gets filled is up to the election's VacancyPolicy
+
+			// if this candidacy has reached the requirement then it has the chance to take a seat
+			if total_vote <= 0.into() || candidacy_new_bucket < fill_requirement { continue; }
+			filled_challengers.push((candidacy_id, total_vote));
+		}
+	}
+
+	// the strongest filled challengers take seats first, each one displacing whoever holds the weakest seat
+	// challengers tied with each other only take seats if there are enough weaker seats for all of them,
+	// otherwise there's no fair way to choose and every seat stays as it is
+	filled_challengers.sort_by(|(a_id, a_total), (b_id, b_total)| b_total.cmp(a_total).then(a_id.cmp(b_id)));
+	let mut remaining_challengers = filled_challengers.as_slice();
+	while let Some((_, group_total_vote)) = remaining_challengers.first() {
+		let group_length = remaining_challengers.iter().take_while(|(_, total_vote)| total_vote == group_total_vote).count();
+		let (group, rest) = remaining_challengers.split_at(group_length);
+		remaining_challengers = rest;
+
+		let weaker_seats = held_seats.iter().take_while(|(_, total_vote)| total_vote < group_total_vote).count();
+		if group.len() > weaker_seats { break; }
+
+		for (challenger_id, total_vote) in group {
+			let (displaced_id, _) = held_seats[0];
+			match displaced_id {
+				Some(displaced_id) => {
+					next.displaced_winners.push(displaced_id);
+					next.statuses.insert(displaced_id, CandidacyStatus::Election(0.into()));
+				},
+				None => { next.filled_empty_seat_ids.push(*challenger_id); },
+			}
+			held_seats[0] = (Some(*challenger_id), *total_vote);
+			sort_weakest_first(&mut held_seats);
+			next.new_winners.push(*challenger_id);
+		}
+	}
+
+	for winner_id in held_seats.iter().filter_map(|(winner_id, _)| *winner_id) {
+		next.statuses.insert(winner_id, CandidacyStatus::Winner);
+	}
+
+	next.displaced_winners.sort();
+	next.removed_candidacy_ids.sort();
+	next
+}
+
+// the strongest challengers in the election stage with positive support, at most open_seats of them
+// a tie that doesn't fit in the open seats isn't broken, everyone in it is passed over
+fn choose_runners_up(candidacy_entries: &[CandidacyEntry], open_seats: usize) -> Vec<usize> {
+	let mut runners_up: Vec<_> = candidacy_entries.iter()
+		.filter(|entry| !entry.is_nomination && entry.total_vote > 0.into())
+		.map(|entry| (entry.candidacy_id, entry.total_vote))
+		.collect();
+	runners_up.sort_by(|(a_id, a_total), (b_id, b_total)| b_total.cmp(a_total).then(a_id.cmp(b_id)));
+
+	let mut chosen = Vec::new();
+	let mut remaining = runners_up.as_slice();
+	while let Some((_, group_total_vote)) = remaining.first() {
+		let group_length = remaining.iter().take_while(|(_, total_vote)| total_vote == group_total_vote).count();
+		let (group, rest) = remaining.split_at(group_length);
+		remaining = rest;
+		if chosen.len() + group.len() > open_seats { break; }
+		chosen.extend(group.iter().map(|(candidacy_id, _)| *candidacy_id));
+	}
+	chosen
+}
+
+// empty seats are weaker than any held seat with the same support, and ties between held seats favor the lower id
+fn sort_weakest_first(held_seats: &mut [(Option<usize>, Weight)]) {
+	held_seats.sort_by(|(a_id, a_total), (b_id, b_total)| {
+		a_total.cmp(b_total).then(a_id.is_some().cmp(&b_id.is_some())).then(b_id.cmp(a_id))
+	});
+}
+
+// None means the candidacy should be removed
+fn apply_negative_bucket_limit(negative_buckets: NegativeBucketsKind, bucket: Weight, fill_requirement: Weight) -> Option<Weight> {
+	if bucket >= 0.into() { return Some(bucket); }
+
+	match negative_buckets {
+		NegativeBucketsKind::None => { Some(Weight::max(bucket, 0.into())) },
+		NegativeBucketsKind::WithoutRemoval => { Some(Weight::max(bucket, -fill_requirement)) },
+		NegativeBucketsKind::WithRemoval => {
+			if bucket <= -fill_requirement { None } else { Some(bucket) }
+		},
+	}
 }
 
 
@@ -516,9 +1714,19 @@ fn validate_allocations(
 	resource_allocations: Vec<ResourceAllocation>,
 	resource_score_allocations: Vec<ResourceScoreAllocation>,
 ) -> Option<(Vec<ResourceAllocation>, Vec<ResourceScoreAllocation>)> {
+	let weights = resource_allocations.iter().map(|a| (a.election_id, a.weight))
+		.chain(resource_score_allocations.iter().flat_map(|a| [(a.election_id, a.approve_weight), (a.election_id, a.disapprove_weight)]));
+	let mut have_negative_weights = false;
+	for (election_id, weight) in weights.filter(|(_, weight)| *weight < 0.into()) {
+		errors.push(PolityActionError::InvalidAllocationWeight{ voter_id: person.id, election_id, weight });
+		have_negative_weights = true;
+	}
+	if have_negative_weights { return None; }
+
 	let found_weight =
 		resource_allocations.iter().map(|a| a.total_weight()).sum::<Weight>()
-		+ resource_score_allocations.iter().map(|a| a.total_weight()).sum::<Weight>();
+		+ resource_score_allocations.iter().map(|a| a.total_weight()).sum::<Weight>()
+		+ delegated_weight(state.delegation_table.get(&person.id));
 	if found_weight > person.given_weight {
 		errors.push(PolityActionError::AboveAllowedWeight{ voter_id: person.id, found_weight, given_weight: person.given_weight });
 		return None;
@@ -562,10 +1770,185 @@ fn validate_allocation<A: Allocation>(
 			have_errors = true;
 		}
 	}
+	for candidacy_id in allocation.iter_fallback_candidacies() {
+		let candidacy = match state.candidacy_table.get(candidacy_id) {
+			Some(candidacy) => candidacy,
+			None => {
+				errors.push(PolityActionError::NoCandidacy{ candidacy_id: *candidacy_id, voter_id });
+				have_errors = true;
+				continue;
+			},
+		};
+		let compatible =
+			if A::fallbacks_stay_in_election() { candidacy.election_id == election_id }
+			else {
+				state.election_table.get(&candidacy.election_id)
+					.map(|fallback_election| fallback_election.selection_method.kind() == expected_method)
+					.unwrap_or(false)
+			};
+		if !compatible {
+			errors.push(PolityActionError::IncompatibleFallback{ voter_id, election_id, candidacy_id: *candidacy_id });
+			have_errors = true;
+		}
+	}
 
 	if !have_errors { Some(allocation) } else { None }
 }
 
+fn delegated_weight(delegations: Option<&Vec<Delegation>>) -> Weight {
+	delegations.map(|delegations| delegations.iter().map(|d| d.weight).sum()).unwrap_or(0.into())
+}
+
+fn validate_delegations(
+	errors: &mut Vec<PolityActionError>,
+	state: &PolityState,
+	person: &StoragePerson,
+	delegations: Vec<Delegation>,
+) -> Option<Vec<Delegation>> {
+	let delegator_id = person.id;
+	let allocated_weight =
+		state.resource_allocation_table.get(&delegator_id).map(|a| a.iter().map(|a| a.total_weight()).sum()).unwrap_or(Weight::ZERO)
+		+ state.resource_score_allocation_table.get(&delegator_id).map(|a| a.iter().map(|a| a.total_weight()).sum()).unwrap_or(Weight::ZERO);
+	let found_weight = allocated_weight + delegated_weight(Some(&delegations));
+	if found_weight > person.given_weight {
+		errors.push(PolityActionError::AboveAllowedWeight{ voter_id: delegator_id, found_weight, given_weight: person.given_weight });
+		return None;
+	}
+
+	let mut have_errors = false;
+	for Delegation{ delegate_id, weight, scope } in &delegations {
+		let delegate_id = *delegate_id;
+		if delegate_id == delegator_id {
+			errors.push(PolityActionError::SelfDelegation{ person_id: delegator_id });
+			have_errors = true;
+			continue;
+		}
+		if *weight <= 0.into() {
+			errors.push(PolityActionError::InvalidDelegationWeight{ delegator_id, delegate_id, weight: *weight });
+			have_errors = true;
+		}
+		if require_present(errors, &state.person_table, &delegate_id).is_none() {
+			have_errors = true;
+			continue;
+		}
+		if let DelegationScope::UnderDocument(document_id) = scope {
+			match require_present(errors, &state.candidacy_table, document_id) {
+				None => { have_errors = true; },
+				Some(candidacy) => {
+					if !matches!(candidacy.content, CandidacyContent::Document{..}) {
+						errors.push(PolityActionError::MismatchedKind{ candidacy_id: *document_id, expected_kind: ElectionKind::Document });
+						have_errors = true;
+					}
+				},
+			}
+		}
+
+		// scopes are ignored here, a cycle through any scopes at all is rejected
+		if delegation_reaches(state, delegate_id, delegator_id) {
+			errors.push(PolityActionError::DelegationCycle{ delegator_id, delegate_id });
+			have_errors = true;
+		}
+	}
+
+	if !have_errors { Some(delegations) } else { None }
+}
+
+// whether following the current delegations from person_id leads back to target_id
+// the target's own delegations are never followed, so they don't matter when they're the ones being replaced
+fn delegation_reaches(state: &PolityState, person_id: usize, target_id: usize) -> bool {
+	let mut visited = HashSet::new();
+	let mut pending = vec![person_id];
+	while let Some(current_id) = pending.pop() {
+		if current_id == target_id { return true; }
+		if !visited.insert(current_id) { continue; }
+		if let Some(delegations) = state.delegation_table.get(&current_id) {
+			pending.extend(delegations.iter().map(|d| d.delegate_id));
+		}
+	}
+	false
+}
+
+fn validate_patch(body: &str, sub_elections: &[InputElection], patch: &DocumentPatch) -> Vec<AmendmentError> {
+	let mut amendment_errors = Vec::new();
+
+	let mut previous_end = 0;
+	let body_length = body.chars().count();
+	for (edit_index, TextEdit{ start, end, .. }) in patch.body_edits.iter().enumerate() {
+		if start < &previous_end || start > end || *end > body_length {
+			amendment_errors.push(AmendmentError::InvalidEdit{ edit_index });
+		}
+		previous_end = usize::max(previous_end, *end);
+	}
+
+	// every existing sub election can be removed or changed once, and added ones can't reuse any id already in use
+	let existing_ids: HashSet<_> = sub_elections.iter().map(|e| e.id).collect();
+	let mut touched_ids = HashSet::new();
+	let touched = patch.removed_election_ids.iter().copied().chain(patch.changed_elections.iter().map(|e| e.id));
+	for election_id in touched {
+		if !existing_ids.contains(&election_id) {
+			amendment_errors.push(AmendmentError::UnknownElection{ election_id });
+		}
+		else if !touched_ids.insert(election_id) {
+			amendment_errors.push(AmendmentError::DuplicateElection{ election_id });
+		}
+	}
+	let mut added_ids = HashSet::new();
+	for election_id in patch.added_elections.iter().map(|e| e.id) {
+		if existing_ids.contains(&election_id) || !added_ids.insert(election_id) {
+			amendment_errors.push(AmendmentError::DuplicateElection{ election_id });
+		}
+	}
+
+	amendment_errors
+}
+
+// the full document an amendment becomes once it wins
+// changed and untouched sub elections are carried over, so amending a document never disturbs the offices it leaves alone
+fn materialize_amendment(amended_content: &CandidacyContent, pitch: &str, patch: &DocumentPatch) -> Option<CandidacyContent> {
+	let (body, sub_elections) = match amended_content {
+		CandidacyContent::Document{ body, sub_elections, .. } => (body, sub_elections),
+		_ => return None,
+	};
+
+	let mut new_body = String::new();
+	let mut chars = body.chars();
+	let mut position = 0;
+	for TextEdit{ start, end, replacement } in &patch.body_edits {
+		new_body.extend(chars.by_ref().take(start - position));
+		chars.by_ref().take(end - start).for_each(drop);
+		new_body.push_str(replacement);
+		position = *end;
+	}
+	new_body.extend(chars);
+
+	let mut new_sub_elections = Vec::new();
+	for sub_election in sub_elections {
+		if patch.removed_election_ids.contains(&sub_election.id) { continue; }
+		let sub_election = patch.changed_elections.iter().find(|e| e.id == sub_election.id).unwrap_or(sub_election);
+		new_sub_elections.push(InputElection{ carries_over: true, ..sub_election.clone() });
+	}
+	for sub_election in &patch.added_elections {
+		new_sub_elections.push(InputElection{ carries_over: false, ..sub_election.clone() });
+	}
+
+	Some(CandidacyContent::Document{ pitch: pitch.to_owned(), body: new_body, sub_elections: new_sub_elections })
+}
+
+// the existing election has to be defined by one of the given documents,
+// and can't change kind or selection method since that would invalidate its candidacies or allocations
+// it also can't lose seats, since there's no fair way to choose which sitting winners would give theirs up
+fn can_carry_over(state: &PolityState, sub_election: &InputElection, document_ids: &[usize]) -> bool {
+	match state.election_table.get(&sub_election.id) {
+		None => false,
+		Some(existing) => {
+			existing.defining_document_id.map(|document_id| document_ids.contains(&document_id)).unwrap_or(false)
+			&& existing.kind == sub_election.kind
+			&& existing.selection_method.kind() == sub_election.selection_method.kind()
+			&& sub_election.seats >= existing.seats
+		},
+	}
+}
+
 fn validate_not_winning_document(
 	errors: &mut Vec<PolityActionError>,
 	status: &CandidacyStatus,
@@ -583,12 +1966,59 @@ fn validate_not_winning_document(
 
 fn validate_candidacy_content(
 	errors: &mut Vec<PolityActionError>,
+	state: &PolityState,
 	content: &CandidacyContent,
-	election_kind: ElectionKind,
+	election: &StorageElection,
 	candidacy_id: usize,
 ) -> Option<()> {
+	let election_kind = election.kind;
 	match (content, election_kind) {
-		(CandidacyContent::Document{..}, ElectionKind::Document) => { Some(()) },
+		(CandidacyContent::Document{ sub_elections, .. }, ElectionKind::Document) => {
+			let mut have_errors = false;
+			for sub_election in sub_elections {
+				if validate_sub_election(errors, candidacy_id, sub_election).is_none() {
+					have_errors = true;
+				}
+				// only an election under the current winner can be carried over, the winner could still change before this wins
+				if sub_election.carries_over {
+					let current_winner_ids: Vec<_> = state.candidacy_table.iter()
+						.filter(|c| c.election_id == election.id && c.status == CandidacyStatus::Winner)
+						.map(|c| c.id)
+						.collect();
+					if !can_carry_over(state, sub_election, &current_winner_ids) {
+						errors.push(PolityActionError::InvalidCarryOver{ candidacy_id, election_id: sub_election.id });
+						have_errors = true;
+					}
+				}
+			}
+			if !have_errors { Some(()) } else { None }
+		},
+		(CandidacyContent::Amendment{ amended_document_id, patch, .. }, ElectionKind::Document) => {
+			let amended_document = state.candidacy_table.get(amended_document_id)
+				.filter(|c| c.election_id == election.id && c.status == CandidacyStatus::Winner);
+			let amendment_errors = match amended_document.map(|c| &c.content) {
+				Some(CandidacyContent::Document{ body, sub_elections, .. }) => validate_patch(body, sub_elections, patch),
+				_ => vec![AmendmentError::NotCurrentWinner{ amended_document_id: *amended_document_id }],
+			};
+			let mut have_errors = !amendment_errors.is_empty();
+			for error in amendment_errors {
+				errors.push(PolityActionError::InvalidAmendment{ candidacy_id, error });
+			}
+			// changed and added elections become live sub elections once the amendment wins, so they're held to the same rules
+			for sub_election in patch.changed_elections.iter().chain(&patch.added_elections) {
+				if validate_sub_election(errors, candidacy_id, sub_election).is_none() {
+					have_errors = true;
+				}
+			}
+			// and changed elections are carried over from the amended document
+			for sub_election in &patch.changed_elections {
+				if amended_document.is_some() && !can_carry_over(state, sub_election, &[*amended_document_id]) {
+					errors.push(PolityActionError::InvalidCarryOver{ candidacy_id, election_id: sub_election.id });
+					have_errors = true;
+				}
+			}
+			if !have_errors { Some(()) } else { None }
+		},
 		(CandidacyContent::Office{..}, ElectionKind::Office) => { Some(()) },
 
 		(_, _) => {
@@ -598,6 +2028,25 @@ fn validate_candidacy_content(
 	}
 }
 
+// the settings every sub election a document defines has to have, whether it's defined directly or through an amendment
+fn validate_sub_election(
+	errors: &mut Vec<PolityActionError>,
+	candidacy_id: usize,
+	sub_election: &InputElection,
+) -> Option<()> {
+	let mut have_errors = false;
+	let seats = sub_election.seats;
+	if seats == 0 || (sub_election.kind == ElectionKind::Document && seats != 1) {
+		errors.push(PolityActionError::InvalidSeats{ candidacy_id, election_id: sub_election.id, seats });
+		have_errors = true;
+	}
+	if let Some(update_frequency) = sub_election.update_frequency.filter(|update_frequency| *update_frequency <= Duration::zero()) {
+		errors.push(PolityActionError::InvalidUpdateFrequency{ candidacy_id, election_id: sub_election.id, update_frequency });
+		have_errors = true;
+	}
+	if !have_errors { Some(()) } else { None }
+}
+
 fn require_not_present<T: Borrow<usize> + TableKindAble + Hash + Eq>(
 	errors: &mut Vec<PolityActionError>,
 	table: &HashSet<T>,
@@ -629,7 +2078,8 @@ fn require_present<'t, T: Borrow<usize> + TableKindAble + Hash + Eq>(
 
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum TableKind {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TableKind {
 	StoragePerson,
 	StorageElection,
 	StorageCandidacy,
@@ -641,19 +2091,20 @@ trait TableKindAble { fn table_kind() -> TableKind; }
 trait IdAble { type Id: Copy + Hash; fn get_id(&self) -> &Self::Id; }
 
 macro_rules! impl_id_traits {
-	($structname: ident) => {
+	// the id type is spelled out rather than taken from IdAble, so the traits can stay private to the crate
+	($structname: ident, $id: ty) => {
 		impl Hash for $structname {
 			fn hash<H: Hasher>(&self, state: &mut H) {
 				self.get_id().hash(state);
 			}
 		}
-		impl Borrow<<$structname as IdAble>::Id> for $structname {
-			fn borrow(&self) -> &<$structname as IdAble>::Id {
+		impl Borrow<$id> for $structname {
+			fn borrow(&self) -> &$id {
 				&self.get_id()
 			}
 		}
-		impl Borrow<<$structname as IdAble>::Id> for &$structname {
-			fn borrow(&self) -> &<$structname as IdAble>::Id {
+		impl Borrow<$id> for &$structname {
+			fn borrow(&self) -> &$id {
 				&self.get_id()
 			}
 		}
@@ -664,69 +2115,188 @@ macro_rules! impl_id_traits {
 }
 
 
-#[derive(Debug, PartialEq, Eq)]
-struct StoragePerson {
-	id: usize,
-	given_weight: Weight,
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StoragePerson {
+	pub id: usize,
+	pub given_weight: Weight,
 	// name: String,
 }
 impl IdAble for StoragePerson { type Id = usize; fn get_id(&self) -> &Self::Id { &self.id } }
-impl_id_traits!(StoragePerson);
-
-#[derive(Debug, PartialEq, Eq)]
-struct StorageElection {
-	id: usize,
-	title: String,
-	description: String,
-	kind: ElectionKind,
-	nomination_fill_method: NominationFillMethod,
-	election_fill_method: ElectionFillMethod,
-	selection_method: SelectionMethod,
-	defining_document_id: Option<usize>,
+impl_id_traits!(StoragePerson, usize);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StorageElection {
+	pub id: usize,
+	pub title: String,
+	pub description: String,
+	pub kind: ElectionKind,
+	pub nomination_fill_method: NominationFillMethod,
+	pub election_fill_method: ElectionFillMethod,
+	pub negative_buckets: NegativeBucketsKind,
+	pub selection_method: SelectionMethod,
+	pub seats: usize,
+	#[cfg_attr(feature = "serde", serde(with = "serde_duration::option"))]
+	pub update_frequency: Option<Duration>,
+	pub vacancy_policy: VacancyPolicy,
+	pub defining_document_id: Option<usize>,
 }
 impl IdAble for StorageElection { type Id = usize; fn get_id(&self) -> &Self::Id { &self.id } }
-impl_id_traits!(StorageElection);
+impl_id_traits!(StorageElection, usize);
 
 
-#[derive(Debug, PartialEq, Eq)]
-struct StorageCandidacy {
-	id: usize,
-	owner_id: usize,
-	election_id: usize,
-	status: CandidacyStatus,
-	content: CandidacyContent,
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StorageCandidacy {
+	pub id: usize,
+	pub owner_id: usize,
+	pub election_id: usize,
+	pub status: CandidacyStatus,
+	pub content: CandidacyContent,
 }
 impl IdAble for StorageCandidacy { type Id = usize; fn get_id(&self) -> &Self::Id { &self.id } }
-impl_id_traits!(StorageCandidacy);
+impl_id_traits!(StorageCandidacy, usize);
 
 
-#[derive(Debug)]
-struct PolityState {
+#[derive(Debug, Clone, PartialEq)]
+// the indexes aren't serialized, they're rebuilt from the tables when the state is read back so they can never disagree with them
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(from = "StoredPolityState"))]
+pub struct PolityState {
 	required_equal_weight: Option<Weight>,
 
+	#[cfg_attr(feature = "serde", serde(serialize_with = "serde_sorted::set"))]
 	person_table: HashSet<StoragePerson>,
 
 	// root_constitution_election: StorageElection,
+	#[cfg_attr(feature = "serde", serde(serialize_with = "serde_sorted::set"))]
 	election_table: HashSet<StorageElection>,
+	#[cfg_attr(feature = "serde", serde(serialize_with = "serde_sorted::set"))]
 	candidacy_table: HashSet<StorageCandidacy>,
 
+	#[cfg_attr(feature = "serde", serde(serialize_with = "serde_sorted::map"))]
+	resource_allocation_table: HashMap<usize, Vec<ResourceAllocation>>,
+	#[cfg_attr(feature = "serde", serde(serialize_with = "serde_sorted::map"))]
+	resource_score_allocation_table: HashMap<usize, Vec<ResourceScoreAllocation>>,
+	#[cfg_attr(feature = "serde", serde(serialize_with = "serde_sorted::map"))]
+	delegation_table: HashMap<usize, Vec<Delegation>>,
+
+	#[cfg_attr(feature = "serde", serde(serialize_with = "serde_sorted::map"))]
+	election_churn_table: HashMap<usize, ElectionChurn>,
+	// every document that has won each document election, oldest first, kept even after the election is removed
+	#[cfg_attr(feature = "serde", serde(serialize_with = "serde_sorted::map"))]
+	enactment_table: HashMap<usize, Vec<Enactment>>,
+	// when each election was last recalculated, elections that have never been are always due
+	#[cfg_attr(feature = "serde", serde(serialize_with = "serde_sorted::map"))]
+	last_recalculated_table: HashMap<usize, DateTime>,
+	// seats left by winners who exited, oldest first
+	#[cfg_attr(feature = "serde", serde(serialize_with = "serde_sorted::map"))]
+	vacancy_table: HashMap<usize, Vec<Vacancy>>,
+	// elections whose next recalculation could change something, the rest are known to already be where a recalculation would leave them
+	#[cfg_attr(feature = "serde", serde(serialize_with = "serde_sorted::set"))]
+	dirty_election_ids: HashSet<usize>,
+
+	// indexes kept alongside the tables so recalculation only has to look at the elections it recalculates
+	// candidacies are indexed by the election they're in even after that election is removed
+	#[cfg_attr(feature = "serde", serde(skip))]
+	candidacy_ids_by_election_id: BTreeMap<usize, BTreeSet<usize>>,
+	#[cfg_attr(feature = "serde", serde(skip))]
+	winner_ids_by_election_id: BTreeMap<usize, BTreeSet<usize>>,
+	#[cfg_attr(feature = "serde", serde(skip))]
+	election_ids_by_defining_document_id: BTreeMap<usize, BTreeSet<usize>>,
+	// voters with an allocation in each election, kept until they reallocate even if the election is removed
+	#[cfg_attr(feature = "serde", serde(skip))]
+	resource_voter_ids_by_election_id: BTreeMap<usize, BTreeSet<usize>>,
+	#[cfg_attr(feature = "serde", serde(skip))]
+	resource_score_voter_ids_by_election_id: BTreeMap<usize, BTreeSet<usize>>,
+	// every voter with an allocation to a missing election or candidacy, or to a candidacy in another election
+	// voters whose allocations have become valid again can be left in, they're dropped whenever they reallocate
+	#[cfg_attr(feature = "serde", serde(skip))]
+	stale_allocation_voter_ids: BTreeSet<usize>,
+
+	// the latest time any action has happened at, starting from the unix epoch
+	latest_occurred_at: DateTime,
+}
+
+// what's actually stored of a PolityState, every field but the indexes in the same order
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct StoredPolityState {
+	required_equal_weight: Option<Weight>,
+	person_table: HashSet<StoragePerson>,
+	election_table: HashSet<StorageElection>,
+	candidacy_table: HashSet<StorageCandidacy>,
 	resource_allocation_table: HashMap<usize, Vec<ResourceAllocation>>,
 	resource_score_allocation_table: HashMap<usize, Vec<ResourceScoreAllocation>>,
+	delegation_table: HashMap<usize, Vec<Delegation>>,
+	election_churn_table: HashMap<usize, ElectionChurn>,
+	enactment_table: HashMap<usize, Vec<Enactment>>,
+	last_recalculated_table: HashMap<usize, DateTime>,
+	vacancy_table: HashMap<usize, Vec<Vacancy>>,
+	dirty_election_ids: HashSet<usize>,
+	latest_occurred_at: DateTime,
+}
+
+#[cfg(feature = "serde")]
+impl From<StoredPolityState> for PolityState {
+	fn from(stored: StoredPolityState) -> PolityState {
+		let mut state = PolityState {
+			required_equal_weight: stored.required_equal_weight,
+			person_table: stored.person_table,
+			election_table: stored.election_table,
+			candidacy_table: stored.candidacy_table,
+			resource_allocation_table: stored.resource_allocation_table,
+			resource_score_allocation_table: stored.resource_score_allocation_table,
+			delegation_table: stored.delegation_table,
+			election_churn_table: stored.election_churn_table,
+			enactment_table: stored.enactment_table,
+			last_recalculated_table: stored.last_recalculated_table,
+			vacancy_table: stored.vacancy_table,
+			dirty_election_ids: stored.dirty_election_ids,
+			candidacy_ids_by_election_id: BTreeMap::new(),
+			winner_ids_by_election_id: BTreeMap::new(),
+			election_ids_by_defining_document_id: BTreeMap::new(),
+			resource_voter_ids_by_election_id: BTreeMap::new(),
+			resource_score_voter_ids_by_election_id: BTreeMap::new(),
+			stale_allocation_voter_ids: BTreeSet::new(),
+			latest_occurred_at: stored.latest_occurred_at,
+		};
+		state.rebuild_indexes();
+		state
+	}
 }
 
-#[derive(Debug, PartialEq)]
-enum PolityStateChange {
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PolityStateChange {
 	InsertPerson{ person_id: usize, given_weight: Weight },
 	SetResourceAllocations{ voter_id: usize, allocations: Vec<ResourceAllocation> },
 	SetResourceScoreAllocations{ voter_id: usize, allocations: Vec<ResourceScoreAllocation> },
+	SetDelegations{ delegator_id: usize, delegations: Vec<Delegation> },
 	RemovePerson{ person_id: usize },
 
 	InsertElection{ election: StorageElection },
+	// an election carried over into a new document, which keeps its id but takes the new document's settings
+	ReplaceElection{ election: StorageElection },
 	RemoveElection{ election_id: usize },
+	SetElectionChurn{ election_id: usize, churn: ElectionChurn },
 
 	InsertCandidacy{ candidacy: StorageCandidacy },
 	SetCandidacyStatus{ candidacy_id: usize, status: CandidacyStatus },
-	RemoveCandidacy{ candidacy_id: usize },
+	SetCandidacyContent{ candidacy_id: usize, content: CandidacyContent },
+	SetCandidacyOwner{ candidacy_id: usize, owner_id: usize },
+	RemoveCandidacy{ candidacy_id: usize, reason: CandidacyRemovalReason },
+
+	RecordEnactment{ election_id: usize, enactment: Enactment },
+	// ends whichever enactment of the election is still in force, if there is one
+	EndEnactment{ election_id: usize, replaced_at: DateTime },
+
+	SetLastRecalculated{ election_id: usize, recalculated_at: DateTime },
+	InsertVacancy{ election_id: usize, vacancy: Vacancy },
+	RemoveVacancy{ election_id: usize, vacated_candidacy_id: usize },
+	// emitted by a recalculation before any of its per election changes, so those changes can mark the elections dirty again
+	MarkElectionsClean{ election_ids: Vec<usize> },
+	AdvanceClock{ occurred_at: DateTime },
 }
 
 // separating changes into a low level makes it possible to use any other persistence layer, as long as we can somehow serialize to that layer
@@ -738,14 +2308,122 @@ impl PolityState {
 	// 	}
 	// }
 
-	fn build() -> PolityStateBuilder { PolityStateBuilder::new() }
+	pub fn build() -> PolityStateBuilder { PolityStateBuilder::new() }
 
 	fn apply_changes(&mut self, changes: Vec<PolityStateChange>) {
 		for change in changes.into_iter() {
+			self.mark_dirty_elections(&change);
 			self.apply_change(change);
 		}
 	}
 
+	// marks every election whose next recalculation could come out differently because of the change
+	// this has to see the state from before the change, to know which elections a voter's old allocations were in
+	// and whether a status actually changed, since recalculation sets every status whether it changed or not
+	fn mark_dirty_elections(&mut self, change: &PolityStateChange) {
+		let mut dirty_election_ids = Vec::new();
+		match change {
+			// the size of the electorate and delegated weight can matter to any election
+			PolityStateChange::InsertPerson{..} | PolityStateChange::RemovePerson{..} | PolityStateChange::SetDelegations{..} => {
+				dirty_election_ids.extend(self.election_table.iter().map(|election| election.id));
+			},
+			PolityStateChange::SetResourceAllocations{ voter_id, allocations } => {
+				let old_allocations = self.resource_allocation_table.get(voter_id).map(Vec::as_slice).unwrap_or_default();
+				dirty_election_ids.extend(changed_allocation_election_ids(old_allocations, allocations));
+			},
+			PolityStateChange::SetResourceScoreAllocations{ voter_id, allocations } => {
+				let old_allocations = self.resource_score_allocation_table.get(voter_id).map(Vec::as_slice).unwrap_or_default();
+				dirty_election_ids.extend(changed_allocation_election_ids(old_allocations, allocations));
+			},
+			PolityStateChange::InsertElection{ election } => {
+				dirty_election_ids.push(election.id);
+			},
+			// the defining document of a carried over election is in the defining document chain of every election under it
+			// and that chain decides which elections delegations scoped to a document cover
+			PolityStateChange::ReplaceElection{ election } => {
+				dirty_election_ids.extend(self.election_subtree_ids(election.id));
+			},
+			PolityStateChange::SetElectionChurn{ election_id, churn } if self.election_churn_table.get(election_id) != Some(churn) => {
+				dirty_election_ids.push(*election_id);
+			},
+			PolityStateChange::InsertCandidacy{ candidacy } => {
+				dirty_election_ids.push(candidacy.election_id);
+			},
+			PolityStateChange::SetCandidacyStatus{ candidacy_id, status } => {
+				if let Some(candidacy) = self.candidacy_table.get(candidacy_id).filter(|candidacy| candidacy.status != *status) {
+					dirty_election_ids.push(candidacy.election_id);
+				}
+			},
+			PolityStateChange::SetCandidacyContent{ candidacy_id, .. } | PolityStateChange::RemoveCandidacy{ candidacy_id, .. } => {
+				dirty_election_ids.extend(self.candidacy_table.get(candidacy_id).map(|candidacy| candidacy.election_id));
+			},
+			PolityStateChange::InsertVacancy{ election_id, .. } | PolityStateChange::RemoveVacancy{ election_id, .. } => {
+				dirty_election_ids.push(*election_id);
+			},
+			_ => {},
+		}
+		self.dirty_election_ids.extend(dirty_election_ids);
+	}
+
+	// the election along with every election defined by a document in it, all the way down
+	fn election_subtree_ids(&self, election_id: usize) -> Vec<usize> {
+		let mut subtree_ids = vec![election_id];
+		let mut visited_ids = HashSet::from([election_id]);
+		let mut index = 0;
+		while let Some(&current_id) = subtree_ids.get(index) {
+			index += 1;
+			for candidacy_id in self.candidacy_ids_by_election_id.get(&current_id).into_iter().flatten() {
+				for &child_id in self.election_ids_by_defining_document_id.get(candidacy_id).into_iter().flatten() {
+					if visited_ids.insert(child_id) { subtree_ids.push(child_id); }
+				}
+			}
+		}
+		subtree_ids
+	}
+
+	// the indexes are derived entirely from the tables, apply_change keeps them up to date from there
+	#[cfg(any(feature = "serde", test))]
+	fn rebuild_indexes(&mut self) {
+		self.candidacy_ids_by_election_id.clear();
+		self.winner_ids_by_election_id.clear();
+		for candidacy in &self.candidacy_table {
+			self.candidacy_ids_by_election_id.entry(candidacy.election_id).or_default().insert(candidacy.id);
+			if candidacy.status == CandidacyStatus::Winner {
+				self.winner_ids_by_election_id.entry(candidacy.election_id).or_default().insert(candidacy.id);
+			}
+		}
+		self.election_ids_by_defining_document_id.clear();
+		for election in &self.election_table {
+			if let Some(document_id) = election.defining_document_id {
+				self.election_ids_by_defining_document_id.entry(document_id).or_default().insert(election.id);
+			}
+		}
+		self.resource_voter_ids_by_election_id.clear();
+		for (voter_id, allocations) in &self.resource_allocation_table {
+			reindex_voter(&mut self.resource_voter_ids_by_election_id, *voter_id, &[], allocations);
+		}
+		self.resource_score_voter_ids_by_election_id.clear();
+		for (voter_id, allocations) in &self.resource_score_allocation_table {
+			reindex_voter(&mut self.resource_score_voter_ids_by_election_id, *voter_id, &[], allocations);
+		}
+		self.stale_allocation_voter_ids = self.resource_allocation_table.keys()
+			.chain(self.resource_score_allocation_table.keys())
+			.copied()
+			.filter(|voter_id| self.has_stale_allocations(*voter_id))
+			.collect();
+	}
+
+	fn has_stale_allocations(&self, voter_id: usize) -> bool {
+		fn is_stale<A: Allocation>(state: &PolityState, allocation: &A) -> bool {
+			let election_id = allocation.get_election_id();
+			!state.election_table.contains(&election_id) || allocation.iter_candidacies().into_iter().any(|candidacy_id| {
+				state.candidacy_table.get(candidacy_id).map(|candidacy| candidacy.election_id != election_id).unwrap_or(true)
+			})
+		}
+		self.resource_allocation_table.get(&voter_id).into_iter().flatten().any(|allocation| is_stale(self, allocation))
+			|| self.resource_score_allocation_table.get(&voter_id).into_iter().flatten().any(|allocation| is_stale(self, allocation))
+	}
+
 	// all of these functions assume validated inputs, calculate_polity_action is responsible for validation
 	fn apply_change(&mut self, change: PolityStateChange) {
 		match change {
@@ -754,47 +2432,189 @@ impl PolityState {
 				self.person_table.insert(person);
 			},
 			PolityStateChange::SetResourceAllocations{ voter_id, allocations } => {
-				self.resource_allocation_table.insert(voter_id, allocations);
+				let old_allocations = self.resource_allocation_table.remove(&voter_id).unwrap_or_default();
+				reindex_voter(&mut self.resource_voter_ids_by_election_id, voter_id, &old_allocations, &allocations);
+				if !allocations.is_empty() { self.resource_allocation_table.insert(voter_id, allocations); }
+				self.restale_voter(voter_id);
 			},
 			PolityStateChange::SetResourceScoreAllocations{ voter_id, allocations } => {
-				self.resource_score_allocation_table.insert(voter_id, allocations);
+				let old_allocations = self.resource_score_allocation_table.remove(&voter_id).unwrap_or_default();
+				reindex_voter(&mut self.resource_score_voter_ids_by_election_id, voter_id, &old_allocations, &allocations);
+				if !allocations.is_empty() { self.resource_score_allocation_table.insert(voter_id, allocations); }
+				self.restale_voter(voter_id);
+			},
+			PolityStateChange::SetDelegations{ delegator_id, delegations } => {
+				if delegations.is_empty() { self.delegation_table.remove(&delegator_id); }
+				else { self.delegation_table.insert(delegator_id, delegations); }
 			},
 			PolityStateChange::RemovePerson{ person_id } => {
 				self.person_table.remove(&person_id);
 			},
 
 			PolityStateChange::InsertElection{ election } => {
+				if let Some(document_id) = election.defining_document_id {
+					self.election_ids_by_defining_document_id.entry(document_id).or_default().insert(election.id);
+				}
+				self.election_table.insert(election);
+			},
+			PolityStateChange::ReplaceElection{ election } => {
+				// the table is keyed by id but equality compares every field, so replace wouldn't find the old election
+				if let Some(document_id) = self.election_table.take(&election.id).and_then(|old_election| old_election.defining_document_id) {
+					remove_indexed(&mut self.election_ids_by_defining_document_id, document_id, election.id);
+				}
+				if let Some(document_id) = election.defining_document_id {
+					self.election_ids_by_defining_document_id.entry(document_id).or_default().insert(election.id);
+				}
 				self.election_table.insert(election);
 			},
 			PolityStateChange::RemoveElection{ election_id } => {
-				self.election_table.remove(&election_id);
+				if let Some(document_id) = self.election_table.take(&election_id).and_then(|election| election.defining_document_id) {
+					remove_indexed(&mut self.election_ids_by_defining_document_id, document_id, election_id);
+				}
+				self.election_churn_table.remove(&election_id);
+				self.last_recalculated_table.remove(&election_id);
+				self.vacancy_table.remove(&election_id);
+				self.dirty_election_ids.remove(&election_id);
+				let voter_ids = self.resource_voter_ids_by_election_id.get(&election_id).into_iter().flatten()
+					.chain(self.resource_score_voter_ids_by_election_id.get(&election_id).into_iter().flatten());
+				self.stale_allocation_voter_ids.extend(voter_ids);
+			},
+			PolityStateChange::SetElectionChurn{ election_id, churn } => {
+				self.election_churn_table.insert(election_id, churn);
 			},
 
 			PolityStateChange::InsertCandidacy{ candidacy } => {
+				self.candidacy_ids_by_election_id.entry(candidacy.election_id).or_default().insert(candidacy.id);
+				if candidacy.status == CandidacyStatus::Winner {
+					self.winner_ids_by_election_id.entry(candidacy.election_id).or_default().insert(candidacy.id);
+				}
 				self.candidacy_table.insert(candidacy);
 			},
 			PolityStateChange::SetCandidacyStatus{ candidacy_id, status } => {
 				if let Some(mut candidacy) = self.candidacy_table.take(&candidacy_id) {
+					if status == CandidacyStatus::Winner {
+						self.winner_ids_by_election_id.entry(candidacy.election_id).or_default().insert(candidacy_id);
+					}
+					else {
+						remove_indexed(&mut self.winner_ids_by_election_id, candidacy.election_id, candidacy_id);
+					}
 					candidacy.status = status;
 					self.candidacy_table.insert(candidacy);
 				}
 			},
-			PolityStateChange::RemoveCandidacy{ candidacy_id } => {
-				self.candidacy_table.remove(&candidacy_id);
+			PolityStateChange::SetCandidacyContent{ candidacy_id, content } => {
+				if let Some(mut candidacy) = self.candidacy_table.take(&candidacy_id) {
+					candidacy.content = content;
+					self.candidacy_table.insert(candidacy);
+				}
+			},
+			PolityStateChange::SetCandidacyOwner{ candidacy_id, owner_id } => {
+				if let Some(mut candidacy) = self.candidacy_table.take(&candidacy_id) {
+					candidacy.owner_id = owner_id;
+					self.candidacy_table.insert(candidacy);
+				}
+			},
+			PolityStateChange::RemoveCandidacy{ candidacy_id, .. } => {
+				let election_id = match self.candidacy_table.take(&candidacy_id) {
+					Some(candidacy) => candidacy.election_id,
+					None => return,
+				};
+				remove_indexed(&mut self.candidacy_ids_by_election_id, election_id, candidacy_id);
+				remove_indexed(&mut self.winner_ids_by_election_id, election_id, candidacy_id);
+
+				// validated allocations to a candidacy are always in its election, so only that election's voters are looked at
+				let mut voter_ids = voter_ids_allocating_to(&self.resource_voter_ids_by_election_id, &self.resource_allocation_table, election_id, candidacy_id);
+				voter_ids.extend(voter_ids_allocating_to(&self.resource_score_voter_ids_by_election_id, &self.resource_score_allocation_table, election_id, candidacy_id));
+				self.stale_allocation_voter_ids.extend(voter_ids);
+			},
+
+			PolityStateChange::RecordEnactment{ election_id, enactment } => {
+				self.enactment_table.entry(election_id).or_default().push(enactment);
+			},
+			PolityStateChange::EndEnactment{ election_id, replaced_at } => {
+				let in_force = self.enactment_table.get_mut(&election_id)
+					.and_then(|enactments| enactments.last_mut())
+					.filter(|enactment| enactment.replaced_at.is_none());
+				if let Some(enactment) = in_force {
+					enactment.replaced_at = Some(replaced_at);
+				}
+			},
+
+			PolityStateChange::SetLastRecalculated{ election_id, recalculated_at } => {
+				self.last_recalculated_table.insert(election_id, recalculated_at);
+			},
+			PolityStateChange::InsertVacancy{ election_id, vacancy } => {
+				self.vacancy_table.entry(election_id).or_default().push(vacancy);
+			},
+			PolityStateChange::RemoveVacancy{ election_id, vacated_candidacy_id } => {
+				if let Some(vacancies) = self.vacancy_table.get_mut(&election_id) {
+					vacancies.retain(|vacancy| vacancy.vacated_candidacy_id != vacated_candidacy_id);
+					if vacancies.is_empty() { self.vacancy_table.remove(&election_id); }
+				}
+			},
+			PolityStateChange::MarkElectionsClean{ election_ids } => {
+				for election_id in election_ids {
+					self.dirty_election_ids.remove(&election_id);
+				}
+			},
+			PolityStateChange::AdvanceClock{ occurred_at } => {
+				self.latest_occurred_at = occurred_at;
 			},
 		}
 	}
+
+	fn restale_voter(&mut self, voter_id: usize) {
+		if self.has_stale_allocations(voter_id) { self.stale_allocation_voter_ids.insert(voter_id); }
+		else { self.stale_allocation_voter_ids.remove(&voter_id); }
+	}
+}
+
+fn reindex_voter<A: Allocation>(
+	voter_ids_by_election_id: &mut BTreeMap<usize, BTreeSet<usize>>,
+	voter_id: usize,
+	old_allocations: &[A],
+	new_allocations: &[A],
+) {
+	for allocation in old_allocations {
+		remove_indexed(voter_ids_by_election_id, allocation.get_election_id(), voter_id);
+	}
+	for allocation in new_allocations {
+		voter_ids_by_election_id.entry(allocation.get_election_id()).or_default().insert(voter_id);
+	}
+}
+
+fn voter_ids_allocating_to<A: Allocation>(
+	voter_ids_by_election_id: &BTreeMap<usize, BTreeSet<usize>>,
+	allocation_table: &HashMap<usize, Vec<A>>,
+	election_id: usize,
+	candidacy_id: usize,
+) -> Vec<usize> {
+	voter_ids_by_election_id.get(&election_id).into_iter().flatten()
+		.filter(|voter_id| {
+			allocation_table.get(voter_id).into_iter().flatten()
+				.any(|allocation| allocation.iter_candidacies().contains(&&candidacy_id))
+		})
+		.copied()
+		.collect()
+}
+
+// empty sets are removed so the indexes only hold keys that have something under them
+fn remove_indexed(index: &mut BTreeMap<usize, BTreeSet<usize>>, key: usize, id: usize) {
+	if let Some(ids) = index.get_mut(&key) {
+		ids.remove(&id);
+		if ids.is_empty() { index.remove(&key); }
+	}
 }
 
 
 #[derive(Debug)]
-struct PolityStateBuilder {
+pub struct PolityStateBuilder {
 	required_equal_weight: Option<Weight>,
 	root_constitution: StorageElection,
 }
 
 impl PolityStateBuilder {
-	fn new() -> PolityStateBuilder {
+	pub fn new() -> PolityStateBuilder {
 		PolityStateBuilder {
 			required_equal_weight: None,
 			root_constitution: StorageElection {
@@ -804,41 +2624,67 @@ impl PolityStateBuilder {
 				kind: ElectionKind::Document,
 				nomination_fill_method: NominationFillMethod::None,
 				election_fill_method: ElectionFillMethod::Constant(100.into()),
+				negative_buckets: NegativeBucketsKind::None,
 				selection_method: SelectionMethod::ResourceScore{ scale_quadratically: false, use_averaging: false },
+				seats: 1,
+				update_frequency: None,
+				vacancy_policy: VacancyPolicy::LeaveEmpty,
 				defining_document_id: None,
 			}
 		}
 	}
-	fn with_required_equal_weight(mut self, required_equal_weight: Weight) -> PolityStateBuilder {
+	pub fn with_required_equal_weight(mut self, required_equal_weight: Weight) -> PolityStateBuilder {
 		self.required_equal_weight = Some(required_equal_weight);
 		self
 	}
-	fn with_resource(mut self) -> PolityStateBuilder {
+	pub fn with_resource(mut self) -> PolityStateBuilder {
 		self.root_constitution.selection_method = SelectionMethod::Resource{ scale_quadratically: false };
 		self
 	}
-	fn with_resource_score(mut self) -> PolityStateBuilder {
+	pub fn with_resource_score(mut self) -> PolityStateBuilder {
 		self.root_constitution.selection_method = SelectionMethod::ResourceScore{ scale_quadratically: false, use_averaging: false };
 		self
 	}
-	fn with_quadratic_resource(mut self) -> PolityStateBuilder {
+	pub fn with_quadratic_resource(mut self) -> PolityStateBuilder {
 		self.root_constitution.selection_method = SelectionMethod::Resource{ scale_quadratically: true };
 		self
 	}
-	fn with_quadratic_resource_score(mut self) -> PolityStateBuilder {
+	pub fn with_quadratic_resource_score(mut self) -> PolityStateBuilder {
 		self.root_constitution.selection_method = SelectionMethod::ResourceScore{ scale_quadratically: true, use_averaging: false };
 		self
 	}
-	fn finish(self) -> PolityState {
+	pub fn with_update_frequency(mut self, update_frequency: Duration) -> PolityStateBuilder {
+		self.root_constitution.update_frequency = Some(update_frequency);
+		self
+	}
+	pub fn finish(self) -> PolityState {
+		let root_constitution_id = self.root_constitution.id;
 		PolityState {
 			required_equal_weight: self.required_equal_weight,
 			person_table: HashSet::new(),
 			election_table: HashSet::from([self.root_constitution]), candidacy_table: HashSet::new(),
 			resource_allocation_table: HashMap::new(), resource_score_allocation_table: HashMap::new(),
+			delegation_table: HashMap::new(),
+			election_churn_table: HashMap::new(),
+			enactment_table: HashMap::new(),
+			last_recalculated_table: HashMap::new(),
+			vacancy_table: HashMap::new(),
+			dirty_election_ids: HashSet::from([root_constitution_id]),
+			candidacy_ids_by_election_id: BTreeMap::new(),
+			winner_ids_by_election_id: BTreeMap::new(),
+			election_ids_by_defining_document_id: BTreeMap::new(),
+			resource_voter_ids_by_election_id: BTreeMap::new(),
+			resource_score_voter_ids_by_election_id: BTreeMap::new(),
+			stale_allocation_voter_ids: BTreeSet::new(),
+			latest_occurred_at: DateTime::UNIX_EPOCH,
 		}
 	}
 }
 
+impl Default for PolityStateBuilder {
+	fn default() -> PolityStateBuilder { PolityStateBuilder::new() }
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -847,11 +2693,12 @@ mod tests {
 	fn test_basic_actions() {
 		let mut state = PolityState::build().finish();
 		let mut errors = Vec::new();
+		let mut report = RecalculationReport::default();
 
 		// success EnterPerson
 		let mut changes = Vec::new(); errors.clear();
 		let action = PolityAction::EnterPerson{ person_id: 1, given_weight: 10.into() };
-		assert!(calculate_polity_action(&state, &mut errors, &mut changes, action).is_some());
+		assert!(calculate_polity_action(&state, &mut errors, &mut changes, &mut report, timestamp(0), action).is_some());
 		assert_eq!(errors, vec![]);
 		assert_eq!(changes, vec![PolityStateChange::InsertPerson{ person_id: 1, given_weight: 10.into() }]);
 		state.apply_changes(changes);
@@ -859,28 +2706,28 @@ mod tests {
 		// fail EnterPerson (id conflict)
 		let mut changes = Vec::new(); errors.clear();
 		let action = PolityAction::EnterPerson{ person_id: 1, given_weight: 10.into() };
-		assert!(calculate_polity_action(&state, &mut errors, &mut changes, action).is_none());
+		assert!(calculate_polity_action(&state, &mut errors, &mut changes, &mut report, timestamp(0), action).is_none());
 		assert_eq!(errors, vec![PolityActionError::IdConflict{ id: 1, table_kind: TableKind::StoragePerson }]);
 		assert_eq!(changes, vec![]);
 
 		// success ExitPerson
 		let mut changes = Vec::new(); errors.clear();
-		let action = PolityAction::ExitPerson{ person_id: 1 };
-		assert!(calculate_polity_action(&state, &mut errors, &mut changes, action).is_some());
+		let action = PolityAction::ExitPerson{ person_id: 1, successor_id: None };
+		assert!(calculate_polity_action(&state, &mut errors, &mut changes, &mut report, timestamp(0), action).is_some());
 		assert_eq!(errors, vec![]);
 		assert_eq!(changes, vec![PolityStateChange::RemovePerson{ person_id: 1 }]);
 
 		// fail ExitPerson (person not found)
 		let mut changes = Vec::new(); errors.clear();
-		let action = PolityAction::ExitPerson{ person_id: 2 };
-		assert!(calculate_polity_action(&state, &mut errors, &mut changes, action).is_none());
+		let action = PolityAction::ExitPerson{ person_id: 2, successor_id: None };
+		assert!(calculate_polity_action(&state, &mut errors, &mut changes, &mut report, timestamp(0), action).is_none());
 		assert_eq!(errors, vec![PolityActionError::NotFound{ id: 2, table_kind: TableKind::StoragePerson }]);
 		assert_eq!(changes, vec![]);
 
 		// success SetAllocations
 		let mut changes = Vec::new(); errors.clear();
 		let action = PolityAction::SetAllocations{ voter_id: 1, resource_allocations: vec![], resource_score_allocations: vec![] };
-		assert!(calculate_polity_action(&state, &mut errors, &mut changes, action).is_some());
+		assert!(calculate_polity_action(&state, &mut errors, &mut changes, &mut report, timestamp(0), action).is_some());
 		assert_eq!(errors, vec![]);
 		assert_eq!(changes, vec![
 			PolityStateChange::SetResourceAllocations{ voter_id: 1, allocations: vec![] },
@@ -890,25 +2737,23 @@ mod tests {
 		// fail SetAllocations (person not found)
 		let mut changes = Vec::new(); errors.clear();
 		let action = PolityAction::SetAllocations{ voter_id: 2, resource_allocations: vec![], resource_score_allocations: vec![] };
-		assert!(calculate_polity_action(&state, &mut errors, &mut changes, action).is_none());
+		assert!(calculate_polity_action(&state, &mut errors, &mut changes, &mut report, timestamp(0), action).is_none());
 		assert_eq!(errors, vec![PolityActionError::NotFound{ id: 2, table_kind: TableKind::StoragePerson }]);
 		assert_eq!(changes, vec![]);
 
 		// success EnterCandidacy (intended winner document under root)
 		let mut changes = Vec::new(); errors.clear();
 		let new_content = CandidacyContent::Document{
-			pitch: "gonna win".into(), body: "".into(), sub_elections: vec![InputElection {
-				id: 1,
+			pitch: "gonna win".into(), body: "".into(), sub_elections: vec![InputElection{
 				title: "gonna win doc".into(),
-				description: "".into(),
-				kind: ElectionKind::Office,
 				selection_method: SelectionMethod::ResourceScore{scale_quadratically: false, use_averaging: false},
 				nomination_fill_method: NominationFillMethod::Constant(10.into()),
 				election_fill_method: ElectionFillMethod::Constant(20.into()),
+				..office_election(1)
 			}],
 		};
 		let action = PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content: new_content.clone() };
-		assert!(calculate_polity_action(&state, &mut errors, &mut changes, action).is_some());
+		assert!(calculate_polity_action(&state, &mut errors, &mut changes, &mut report, timestamp(0), action).is_some());
 		assert_eq!(errors, vec![]);
 		assert_eq!(changes, vec![
 			PolityStateChange::InsertCandidacy{ candidacy: StorageCandidacy {
@@ -939,6 +2784,112 @@ mod tests {
 		// fail ExitCandidacy (document) (winner)
 	}
 
+	#[test]
+	fn test_recalculation_sees_every_candidacy() {
+		// an election's only candidacy used to be dropped when grouping candidacies by election
+		let mut polity = Polity::new(PolityState::build().finish());
+		polity.perform(PolityAction::EnterPerson{ person_id: 1, given_weight: 10.into() }).unwrap();
+		let content = document(vec![]);
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content }).unwrap();
+
+		let outcome = polity.perform(PolityAction::Recalculate).unwrap();
+		assert!(outcome.changes.iter().any(|change| matches!(change, PolityStateChange::SetCandidacyStatus{ candidacy_id: 10, .. })));
+	}
+
+	#[test]
+	fn test_document_winner_kept_without_new_winner() {
+		// the current document used to be deleted whenever a recalculation didn't produce a new winner
+		let mut state = PolityState::build().finish();
+		state.apply_changes(vec![
+			PolityStateChange::InsertPerson{ person_id: 1, given_weight: 10.into() },
+			PolityStateChange::InsertCandidacy{ candidacy: StorageCandidacy{
+				id: 10, owner_id: 1, election_id: 0, status: CandidacyStatus::Winner,
+				content: document(vec![]),
+			} },
+		]);
+		let mut polity = Polity::new(state);
+
+		polity.perform(PolityAction::Recalculate).unwrap();
+		assert_eq!(polity.election_winners(0).map(|c| c.id).collect::<Vec<_>>(), vec![10]);
+	}
+
+	#[test]
+	fn test_replaced_document_removes_elections_in_id_order() {
+		// the elections under a replaced document used to be removed in whatever order the election table iterated in
+		let mut polity = polity_with_document(document((1..=8).map(office_election).collect()));
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 11, owner_id: 1, election_id: 0, content: document(vec![]) }).unwrap();
+		polity.perform(allocate(1, vec![(0, 11, 50)])).unwrap();
+
+		let mut removed_election_ids = Vec::new();
+		for _ in 0..2 {
+			let outcome = polity.perform(PolityAction::Recalculate).unwrap();
+			removed_election_ids.extend(outcome.changes.iter().filter_map(|change| match change {
+				PolityStateChange::RemoveElection{ election_id } => Some(*election_id),
+				_ => None,
+			}));
+		}
+		assert_eq!(removed_election_ids, (1..=8).collect::<Vec<_>>());
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn test_serde_json_representation() {
+		use serde_json::json;
+
+		let action = PolityAction::SetAllocations{ voter_id: 1, resource_allocations: vec![
+			ResourceAllocation{ election_id: 0, candidacy_id: 10, weight: "0.1000000000000000000000000001".parse().unwrap(), fallback_candidacy_ids: vec![] },
+		], resource_score_allocations: vec![] };
+		let value = serde_json::to_value(&action).unwrap();
+		assert_eq!(value, json!({ "SetAllocations": {
+			"voter_id": 1,
+			"resource_allocations": [{ "election_id": 0, "candidacy_id": 10, "weight": "0.1000000000000000000000000001", "fallback_candidacy_ids": [] }],
+			"resource_score_allocations": [],
+		} }));
+		let round_tripped: PolityAction = serde_json::from_value(value).unwrap();
+		assert_eq!(format!("{:?}", round_tripped), format!("{:?}", action));
+
+		let status = CandidacyStatus::Nomination("2.50".parse().unwrap());
+		assert_eq!(serde_json::to_string(&status).unwrap(), r#"{"Nomination":"2.50"}"#);
+
+		let mut state = PolityState::build().with_required_equal_weight(10.into()).finish();
+		state.apply_changes(vec![
+			PolityStateChange::InsertPerson{ person_id: 1, given_weight: 10.into() },
+			PolityStateChange::SetResourceScoreAllocations{ voter_id: 1, allocations: vec![
+				ResourceScoreAllocation{ election_id: 0, approve_weight: 4.into(), disapprove_weight: 1.into(), scores: HashMap::from([(3, (-1).into())]), fallback_candidacy_ids: HashMap::new() },
+			] },
+		]);
+		let round_tripped: PolityState = serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+		assert_eq!(round_tripped, state);
+		// the indexes aren't written, they're rebuilt, including the voter whose score points at a missing candidacy
+		let value = serde_json::to_value(&state).unwrap();
+		assert_eq!(value.get("stale_allocation_voter_ids"), None);
+		assert_eq!(round_tripped.stale_allocation_voter_ids, BTreeSet::from([1]));
+
+		// every table is written in id order, so equal states always serialize the same no matter how their tables iterate
+		let make_state = || {
+			let mut state = PolityState::build().finish();
+			state.apply_changes((1..=16).map(|person_id| PolityStateChange::InsertPerson{ person_id, given_weight: 10.into() }).collect());
+			state.apply_changes(vec![PolityStateChange::SetResourceScoreAllocations{ voter_id: 1, allocations: vec![
+				ResourceScoreAllocation{
+					election_id: 0, approve_weight: 4.into(), disapprove_weight: 1.into(),
+					scores: (1..=16).map(|candidacy_id| (candidacy_id, 1.into())).collect(), fallback_candidacy_ids: HashMap::new(),
+				},
+			] }]);
+			state
+		};
+		let json = serde_json::to_string(&make_state()).unwrap();
+		for _ in 0..4 {
+			assert_eq!(serde_json::to_string(&make_state()).unwrap(), json);
+		}
+
+		let entry = PolityActionEntry{ occurred_at: timestamp(86400), change: PolityAction::RecalculateDue };
+		assert_eq!(serde_json::to_value(&entry).unwrap(), json!({ "occurred_at": "1970-01-02T00:00:00Z", "change": "RecalculateDue" }));
+		let error = PolityActionError::InvalidUpdateFrequency{ candidacy_id: 1, election_id: 2, update_frequency: Duration::milliseconds(-1500) };
+		let value = serde_json::to_value(&error).unwrap();
+		assert_eq!(value["InvalidUpdateFrequency"]["update_frequency"], json!([-1, -500000000]));
+		assert_eq!(serde_json::from_value::<PolityActionError>(value).unwrap(), error);
+	}
+
 	// some possible properties
 	// - it's impossible to do anything for a person/candidate/election that doesn't exist
 	// - id conflicts are always prevented
@@ -948,18 +2899,936 @@ mod tests {
 
 	// in general most tests will focus around perform_polity_recalculation, but especially perform_election_recalculation and calculate_next_statuses
 
-	// #[test]
-	// fn test_calculate_next_statuses() {
-	// 	// CandidacyEntry {
-	// 	// 	candidacy_id: usize,
-	// 	// 	is_nomination: bool,
-	// 	// 	bucket: Weight,
-	// 	// 	total_vote: Weight,
-	// 	// }
-
-	// 	let (new_winner, candidacy_new_statuses) =
-	// 		calculate_next_statuses(nomination_fill_requirement, election_fill_requirement, current_winner, candidacy_entries);
-	// }
+	fn entry(candidacy_id: usize, is_nomination: bool, bucket: i64, total_vote: i64) -> CandidacyEntry {
+		let fill_requirement = if is_nomination { 10.into() } else { 20.into() };
+		CandidacyEntry{ candidacy_id, is_nomination, bucket: bucket.into(), total_vote: total_vote.into(), fill_requirement }
+	}
+
+	#[test]
+	fn test_calculate_next_statuses() {
+		// nomination buckets only promote once they reach the requirement
+		let next = calculate_next_statuses(1, NegativeBucketsKind::None, vec![], vec![
+			entry(1, true, 0, 6), entry(2, true, 6, 6), entry(3, true, 0, -3),
+		]);
+		assert_eq!(next.new_winners, Vec::<usize>::new());
+		assert_eq!(next.statuses, HashMap::from([
+			(1, CandidacyStatus::Nomination(6.into())),
+			(2, CandidacyStatus::Election(0.into())),
+			(3, CandidacyStatus::Nomination(0.into())),
+		]));
+
+		// election buckets fill by how far a candidacy is above the current winner
+		let next = calculate_next_statuses(1, NegativeBucketsKind::None, vec![(9, 5.into())], vec![
+			entry(1, false, 0, 15), entry(2, false, 8, 10), entry(3, false, 4, 1),
+		]);
+		assert_eq!(next.new_winners, Vec::<usize>::new());
+		assert_eq!(next.statuses, HashMap::from([
+			(1, CandidacyStatus::Election(10.into())),
+			(2, CandidacyStatus::Election(13.into())),
+			(3, CandidacyStatus::Election(0.into())),
+			(9, CandidacyStatus::Winner),
+		]));
+
+		// only a unique filled candidacy becomes the winner
+		let next = calculate_next_statuses(1, NegativeBucketsKind::None, vec![(9, 5.into())], vec![
+			entry(1, false, 10, 15), entry(2, false, 13, 10),
+		]);
+		assert_eq!(next.new_winners, vec![1]);
+		assert_eq!(next.displaced_winners, vec![9]);
+		assert_eq!(next.statuses.get(&1), Some(&CandidacyStatus::Winner));
+		assert_eq!(next.statuses.get(&2), Some(&CandidacyStatus::Election(18.into())));
+		assert_eq!(next.statuses.get(&9), Some(&CandidacyStatus::Election(0.into())));
+
+		let next = calculate_next_statuses(1, NegativeBucketsKind::None, vec![], vec![
+			entry(1, false, 20, 15), entry(2, false, 20, 15),
+		]);
+		assert_eq!(next.new_winners, Vec::<usize>::new());
+	}
+
+	#[test]
+	fn test_multi_seat_statuses() {
+		// buckets fill against the weakest seat, which is empty here
+		let next = calculate_next_statuses(3, NegativeBucketsKind::None, vec![(9, 30.into())], vec![
+			entry(1, false, 15, 8), entry(2, false, 0, 5),
+		]);
+		assert_eq!(next.statuses.get(&1), Some(&CandidacyStatus::Winner));
+		assert_eq!(next.statuses.get(&2), Some(&CandidacyStatus::Election(5.into())));
+		assert_eq!(next.statuses.get(&9), Some(&CandidacyStatus::Winner));
+		assert_eq!(next.new_winners, vec![1]);
+		assert_eq!(next.displaced_winners, Vec::<usize>::new());
+
+		// every seat is held, so challengers fill against the weakest incumbent and displace it
+		let incumbents = || vec![(7, 12.into()), (8, 4.into()), (9, 30.into())];
+		let next = calculate_next_statuses(3, NegativeBucketsKind::None, incumbents(), vec![
+			entry(1, false, 18, 6), entry(2, false, 0, 20),
+		]);
+		assert_eq!(next.new_winners, vec![1]);
+		assert_eq!(next.displaced_winners, vec![8]);
+		assert_eq!(next.statuses.get(&2), Some(&CandidacyStatus::Election(16.into())));
+		assert_eq!(next.statuses.get(&7), Some(&CandidacyStatus::Winner));
+		assert_eq!(next.statuses.get(&8), Some(&CandidacyStatus::Election(0.into())));
+
+		// two filled challengers take the two weakest seats, strongest first
+		let next = calculate_next_statuses(3, NegativeBucketsKind::None, incumbents(), vec![
+			entry(1, false, 20, 15), entry(2, false, 20, 25),
+		]);
+		assert_eq!(next.new_winners, vec![2, 1]);
+		assert_eq!(next.displaced_winners, vec![7, 8]);
+		assert_eq!(next.statuses.get(&9), Some(&CandidacyStatus::Winner));
+
+		// a challenger only takes a seat it actually beats
+		let next = calculate_next_statuses(3, NegativeBucketsKind::None, incumbents(), vec![
+			entry(1, false, 20, 15), entry(2, false, 20, 10),
+		]);
+		assert_eq!(next.new_winners, vec![1]);
+		assert_eq!(next.displaced_winners, vec![8]);
+		assert_eq!(next.statuses.get(&2), Some(&CandidacyStatus::Election(26.into())));
+		assert_eq!(next.statuses.get(&7), Some(&CandidacyStatus::Winner));
+
+		// tied challengers only get in if there are enough weaker seats for all of them
+		let tied = || vec![entry(1, false, 20, 10), entry(2, false, 20, 10)];
+		assert_eq!(calculate_next_statuses(3, NegativeBucketsKind::None, incumbents(), tied()).new_winners, Vec::<usize>::new());
+		assert_eq!(calculate_next_statuses(3, NegativeBucketsKind::None, vec![(9, 30.into())], tied()).new_winners, vec![1, 2]);
+
+		let mut polity = Polity::new(PolityState::build().finish());
+		polity.perform(PolityAction::EnterPerson{ person_id: 1, given_weight: 10.into() }).unwrap();
+		let sub_election = |id: usize, kind: ElectionKind, seats: usize| InputElection{
+			kind, seats, selection_method: SelectionMethod::ResourceScore{ scale_quadratically: false, use_averaging: false },
+			..office_election(id)
+		};
+		let content = document(vec![
+			sub_election(1, ElectionKind::Office, 5), sub_election(2, ElectionKind::Office, 0), sub_election(3, ElectionKind::Document, 2),
+		]);
+		let errors = polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content }).unwrap_err();
+		assert_eq!(errors, vec![
+			PolityActionError::InvalidSeats{ candidacy_id: 10, election_id: 2, seats: 0 },
+			PolityActionError::InvalidSeats{ candidacy_id: 10, election_id: 3, seats: 2 },
+		]);
+	}
+
+	#[test]
+	fn test_proportional_resource_score() {
+		let score_allocation = |approve_weight: i64, scores: Vec<(usize, i64)>| ResourceScoreAllocation{
+			election_id: 0, approve_weight: approve_weight.into(), disapprove_weight: 0.into(),
+			scores: scores.into_iter().map(|(candidacy_id, score)| (candidacy_id, score.into())).collect(),
+			fallback_candidacy_ids: HashMap::new(),
+		};
+		// a majority faction of 6 likes 1 and 2, a minority of 4 likes 3
+		let allocations = [
+			score_allocation(6, vec![(1, 1), (2, 1)]),
+			score_allocation(4, vec![(3, 1)]),
+		];
+		let allocations: Vec<_> = allocations.iter().collect();
+
+		// plain summing hands the majority both seats
+		assert_eq!(aggregate_resource_score_votes(&allocations, false), HashMap::from([
+			(1, 6.into()), (2, 6.into()), (3, 4.into()),
+		]));
+		// once 1 is picked the majority only counts half as much
+		assert_eq!(aggregate_proportional_resource_score_votes(&allocations, 2, false, false), HashMap::from([
+			(1, 6.into()), (3, 4.into()), (2, 3.into()),
+		]));
+		// candidacies that weren't picked are left with whatever support isn't already represented
+		assert_eq!(aggregate_proportional_resource_score_votes(&allocations, 1, false, false), HashMap::from([
+			(1, 6.into()), (2, 3.into()), (3, 4.into()),
+		]));
+		assert_eq!(
+			aggregate_proportional_resource_score_votes(&allocations, 1, false, true),
+			aggregate_resource_score_votes(&allocations, true),
+		);
+	}
+
+	#[test]
+	fn test_integrity_warnings() {
+		let document = |id: usize, election_id: usize, status: CandidacyStatus| StorageCandidacy{
+			id, owner_id: 1, election_id, status,
+			content: document(vec![]),
+		};
+		let mut state = PolityState::build().with_resource().finish();
+		state.apply_changes(vec![
+			PolityStateChange::InsertPerson{ person_id: 1, given_weight: 10.into() },
+			PolityStateChange::InsertCandidacy{ candidacy: document(10, 0, CandidacyStatus::Winner) },
+			PolityStateChange::InsertCandidacy{ candidacy: document(11, 0, CandidacyStatus::Winner) },
+			PolityStateChange::InsertCandidacy{ candidacy: document(12, 5, CandidacyStatus::Election(0.into())) },
+			PolityStateChange::SetResourceAllocations{ voter_id: 1, allocations: vec![
+				ResourceAllocation{ election_id: 0, candidacy_id: 10, weight: 1.into(), fallback_candidacy_ids: vec![] },
+				ResourceAllocation{ election_id: 0, candidacy_id: 13, weight: 1.into(), fallback_candidacy_ids: vec![] },
+				ResourceAllocation{ election_id: 0, candidacy_id: 12, weight: 1.into(), fallback_candidacy_ids: vec![] },
+				ResourceAllocation{ election_id: 6, candidacy_id: 10, weight: 1.into(), fallback_candidacy_ids: vec![] },
+			] },
+		]);
+
+		let warnings = vec![
+			IntegrityWarning::AllocationToMissingCandidacy{ voter_id: 1, election_id: 0, candidacy_id: 13 },
+			IntegrityWarning::MismatchedAllocationElection{ voter_id: 1, candidacy_id: 12, allocation_election_id: 0, candidacy_election_id: 5 },
+			IntegrityWarning::AllocationToMissingElection{ voter_id: 1, election_id: 6 },
+			IntegrityWarning::TooManyWinners{ election_id: 0, seats: 1, winner_ids: vec![10, 11] },
+			IntegrityWarning::CandidacyWithoutElection{ candidacy_id: 12, election_id: 5 },
+		];
+		let outcome = Polity::new(state.clone()).calculate(PolityAction::Recalculate).unwrap();
+		assert_eq!(outcome.report.warnings, warnings);
+		// elections that aren't recalculated are still checked
+		state.dirty_election_ids.clear();
+		let outcome = Polity::new(state).calculate(PolityAction::Recalculate).unwrap();
+		assert_eq!(outcome.report.warnings, warnings);
+
+		let outcome = Polity::new(PolityState::build().finish()).calculate(PolityAction::Recalculate).unwrap();
+		assert_eq!(outcome.report, RecalculationReport::default());
+	}
+
+	#[test]
+	fn test_orphaned_allocations() {
+		let mut polity = polity_with_document(document(vec![office_election(1)]));
+		polity.perform(PolityAction::EnterPerson{ person_id: 2, given_weight: 100.into() }).unwrap();
+		assert!(polity.election(1).is_some());
+
+		let content = CandidacyContent::Office{ pitch: "".into() };
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 20, owner_id: 2, election_id: 1, content: content.clone() }).unwrap();
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 21, owner_id: 1, election_id: 1, content }).unwrap();
+		polity.perform(allocate(2, vec![(1, 20, 5), (1, 21, 7)])).unwrap();
+
+		let outcome = polity.perform(PolityAction::ExitCandidacy{ candidacy_id: 20 }).unwrap();
+		assert_eq!(outcome.orphaned_allocations, vec![
+			OrphanedAllocation{ voter_id: 2, election_id: 1, candidacy_id: Some(20), stranded_weight: 5.into() },
+		]);
+
+		// replacing the winning document cascades through its sub election
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 11, owner_id: 1, election_id: 0, content: document(vec![]) }).unwrap();
+		polity.perform(allocate(1, vec![(0, 10, 1), (0, 11, 49)])).unwrap();
+		for _ in 0..2 {
+			let outcome = polity.perform(PolityAction::Recalculate).unwrap();
+			assert_eq!(outcome.orphaned_allocations, vec![]);
+		}
+		let outcome = polity.perform(PolityAction::Recalculate).unwrap();
+		assert_eq!(outcome.orphaned_allocations, vec![
+			OrphanedAllocation{ voter_id: 1, election_id: 0, candidacy_id: Some(10), stranded_weight: 1.into() },
+			OrphanedAllocation{ voter_id: 2, election_id: 1, candidacy_id: None, stranded_weight: 7.into() },
+		]);
+	}
+
+	#[test]
+	fn test_orphaned_score_allocations() {
+		let sub_election = InputElection{ selection_method: SelectionMethod::ResourceScore{ scale_quadratically: false, use_averaging: false }, ..office_election(1) };
+		let mut polity = polity_with_document(document(vec![sub_election]));
+		polity.perform(PolityAction::EnterPerson{ person_id: 2, given_weight: 100.into() }).unwrap();
+
+		for candidacy_id in [20, 21, 22] {
+			polity.perform(PolityAction::EnterCandidacy{ candidacy_id, owner_id: 2, election_id: 1, content: CandidacyContent::Office{ pitch: "".into() } }).unwrap();
+		}
+		polity.perform(PolityAction::SetAllocations{ voter_id: 2, resource_allocations: vec![], resource_score_allocations: vec![
+			ResourceScoreAllocation{
+				election_id: 1, approve_weight: 6.into(), disapprove_weight: 4.into(),
+				scores: HashMap::from([(20, 1.into()), (21, 1.into()), (22, (-1).into())]), fallback_candidacy_ids: HashMap::new(),
+			},
+		] }).unwrap();
+		let outcome = polity.perform(PolityAction::ExitCandidacy{ candidacy_id: 22 }).unwrap();
+		assert_eq!(outcome.orphaned_allocations, vec![
+			OrphanedAllocation{ voter_id: 2, election_id: 1, candidacy_id: Some(22), stranded_weight: 4.into() },
+		]);
+
+		// the disapprove weight was already reported with candidacy 22, so only the approve weight is stranded with the election
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 11, owner_id: 1, election_id: 0, content: document(vec![]) }).unwrap();
+		polity.perform(allocate(1, vec![(0, 11, 50)])).unwrap();
+		let mut orphaned_allocations = Vec::new();
+		for _ in 0..2 {
+			orphaned_allocations.extend(polity.perform(PolityAction::Recalculate).unwrap().orphaned_allocations);
+		}
+		assert_eq!(orphaned_allocations, vec![
+			OrphanedAllocation{ voter_id: 2, election_id: 1, candidacy_id: None, stranded_weight: 6.into() },
+		]);
+	}
+
+	#[test]
+	fn test_fallback_allocations() {
+		let mut polity = polity_with_document(document(vec![office_election(1)]));
+		let content = CandidacyContent::Office{ pitch: "".into() };
+		for candidacy_id in [20, 21, 22] {
+			polity.perform(PolityAction::EnterCandidacy{ candidacy_id, owner_id: 1, election_id: 1, content: content.clone() }).unwrap();
+		}
+
+		let errors = polity.perform(PolityAction::SetAllocations{ voter_id: 1, resource_allocations: vec![
+			ResourceAllocation{ election_id: 1, candidacy_id: 20, weight: 5.into(), fallback_candidacy_ids: vec![99] },
+		], resource_score_allocations: vec![] }).unwrap_err();
+		assert_eq!(errors, vec![PolityActionError::NoCandidacy{ candidacy_id: 99, voter_id: 1 }]);
+
+		polity.perform(PolityAction::SetAllocations{ voter_id: 1, resource_allocations: vec![
+			ResourceAllocation{ election_id: 0, candidacy_id: 10, weight: 50.into(), fallback_candidacy_ids: vec![] },
+			ResourceAllocation{ election_id: 1, candidacy_id: 20, weight: 5.into(), fallback_candidacy_ids: vec![21, 22] },
+		], resource_score_allocations: vec![] }).unwrap();
+		polity.perform(PolityAction::ExitCandidacy{ candidacy_id: 21 }).unwrap();
+		polity.perform(PolityAction::ExitCandidacy{ candidacy_id: 20 }).unwrap();
+
+		let outcome = polity.perform(PolityAction::Recalculate).unwrap();
+		assert_eq!(outcome.report.fallback_moves, vec![
+			FallbackMove{ voter_id: 1, from_candidacy_id: 20, to_candidacy_id: 22, weight: 5.into() },
+		]);
+		assert_eq!(polity.state().resource_allocation_table[&1][1], ResourceAllocation{
+			election_id: 1, candidacy_id: 22, weight: 5.into(), fallback_candidacy_ids: vec![],
+		});
+		// the moved weight already counted
+		assert_eq!(polity.candidacy(22).unwrap().status, CandidacyStatus::Election(5.into()));
+		assert_eq!(polity.perform(PolityAction::Recalculate).unwrap().report.fallback_moves, vec![]);
+
+		// scores only move to candidacies that haven't been scored already
+		let mut polity = Polity::new(PolityState::build().finish());
+		polity.perform(PolityAction::EnterPerson{ person_id: 1, given_weight: 100.into() }).unwrap();
+		for candidacy_id in [10, 11, 12] {
+			polity.perform(PolityAction::EnterCandidacy{ candidacy_id, owner_id: 1, election_id: 0, content: document(vec![]) }).unwrap();
+		}
+		polity.perform(PolityAction::SetAllocations{ voter_id: 1, resource_allocations: vec![], resource_score_allocations: vec![
+			ResourceScoreAllocation{
+				election_id: 0, approve_weight: 30.into(), disapprove_weight: 10.into(),
+				scores: HashMap::from([(10, 1.into()), (11, (-1).into())]),
+				fallback_candidacy_ids: HashMap::from([(10, vec![11, 12])]),
+			},
+		] }).unwrap();
+		polity.perform(PolityAction::ExitCandidacy{ candidacy_id: 10 }).unwrap();
+		let outcome = polity.perform(PolityAction::Recalculate).unwrap();
+		assert_eq!(outcome.report.fallback_moves, vec![
+			FallbackMove{ voter_id: 1, from_candidacy_id: 10, to_candidacy_id: 12, weight: 30.into() },
+		]);
+		assert_eq!(polity.state().resource_score_allocation_table[&1][0].scores, HashMap::from([(11, (-1).into()), (12, 1.into())]));
+	}
+
+	#[test]
+	fn test_delegations() {
+		let mut polity = Polity::new(PolityState::build().with_resource().finish());
+		for person_id in [1, 2, 3] {
+			polity.perform(PolityAction::EnterPerson{ person_id, given_weight: 100.into() }).unwrap();
+		}
+		let delegate = |delegate_id: usize, weight: i64, scope: DelegationScope| Delegation{ delegate_id, weight: weight.into(), scope };
+		let everything = DelegationScope::Everything;
+
+		let errors = polity.perform(PolityAction::SetDelegations{ delegator_id: 1, delegations: vec![
+			delegate(1, 10, everything), delegate(9, 10, everything), delegate(2, 0, everything),
+		] }).unwrap_err();
+		assert_eq!(errors, vec![
+			PolityActionError::SelfDelegation{ person_id: 1 },
+			PolityActionError::NotFound{ id: 9, table_kind: TableKind::StoragePerson },
+			PolityActionError::InvalidDelegationWeight{ delegator_id: 1, delegate_id: 2, weight: 0.into() },
+		]);
+		let errors = polity.perform(PolityAction::SetDelegations{ delegator_id: 1, delegations: vec![delegate(2, 101, everything)] }).unwrap_err();
+		assert_eq!(errors, vec![PolityActionError::AboveAllowedWeight{ voter_id: 1, found_weight: 101.into(), given_weight: 100.into() }]);
+
+		// 3 ends up voting with their own weight, half of 2's own weight, and half of what 1 delegated to 2
+		polity.perform(PolityAction::SetDelegations{ delegator_id: 1, delegations: vec![delegate(2, 100, everything)] }).unwrap();
+		polity.perform(PolityAction::SetDelegations{ delegator_id: 2, delegations: vec![delegate(3, 50, everything)] }).unwrap();
+		let errors = polity.perform(PolityAction::SetDelegations{ delegator_id: 3, delegations: vec![delegate(1, 10, everything)] }).unwrap_err();
+		assert_eq!(errors, vec![PolityActionError::DelegationCycle{ delegator_id: 3, delegate_id: 1 }]);
+		assert_eq!(
+			calculate_delegation_amplifications(polity.state(), 0),
+			HashMap::from([(1, 1.into()), (2, 2.into()), (3, 2.into())]),
+		);
+
+		// delegated weight can't also be allocated directly
+		let errors = polity.perform(allocate(2, vec![(0, 10, 60)])).unwrap_err();
+		assert_eq!(errors, vec![PolityActionError::AboveAllowedWeight{ voter_id: 2, found_weight: 110.into(), given_weight: 100.into() }]);
+
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content: document(vec![]) }).unwrap();
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 11, owner_id: 1, election_id: 0, content: document(vec![office_election(1)]) }).unwrap();
+		polity.perform(allocate(2, vec![(0, 10, 50)])).unwrap();
+		polity.perform(allocate(3, vec![(0, 11, 100)])).unwrap();
+		polity.perform(PolityAction::Recalculate).unwrap();
+		assert_eq!(polity.candidacy(10).unwrap().status, CandidacyStatus::Election(100.into()));
+		assert_eq!(polity.candidacy(11).unwrap().status, CandidacyStatus::Winner);
+
+		// a scoped delegation only counts in elections under that document
+		polity.perform(PolityAction::SetDelegations{ delegator_id: 2, delegations: vec![delegate(3, 50, DelegationScope::UnderDocument(11))] }).unwrap();
+		assert_eq!(calculate_delegation_amplifications(polity.state(), 0), HashMap::from([(1, 1.into()), (2, 2.into())]));
+		assert_eq!(calculate_delegation_amplifications(polity.state(), 1), HashMap::from([(1, 1.into()), (2, 2.into()), (3, 2.into())]));
+
+		polity.perform(PolityAction::SetDelegations{ delegator_id: 2, delegations: vec![] }).unwrap();
+		assert_eq!(polity.delegations(2), &[]);
+		assert_eq!(polity.delegations(1), &[delegate(2, 100, everything)]);
+	}
+
+	#[test]
+	fn test_person_exit_cascade() {
+		let mut polity = Polity::new(PolityState::build().with_resource().finish());
+		for person_id in 1..=3 {
+			polity.perform(PolityAction::EnterPerson{ person_id, given_weight: 100.into() }).unwrap();
+		}
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content: document(vec![office_election(1)]) }).unwrap();
+		polity.perform(allocate(2, vec![(0, 10, 50)])).unwrap();
+		for _ in 0..2 { polity.perform(PolityAction::Recalculate).unwrap(); }
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 11, owner_id: 1, election_id: 0, content: document(vec![]) }).unwrap();
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 20, owner_id: 1, election_id: 1, content: CandidacyContent::Office{ pitch: "".into() } }).unwrap();
+		polity.perform(allocate(2, vec![(0, 10, 50), (1, 20, 10)])).unwrap();
+		polity.perform(allocate(1, vec![(1, 20, 5)])).unwrap();
+		polity.perform(PolityAction::Recalculate).unwrap();
+		assert_eq!(polity.election_winners(1).map(|c| c.id).collect::<Vec<_>>(), vec![20]);
+		let delegation = Delegation{ delegate_id: 1, weight: 10.into(), scope: DelegationScope::Everything };
+		polity.perform(PolityAction::SetDelegations{ delegator_id: 3, delegations: vec![delegation] }).unwrap();
+
+		let errors = polity.perform(PolityAction::ExitPerson{ person_id: 1, successor_id: None }).unwrap_err();
+		assert_eq!(errors, vec![PolityActionError::SuccessorRequired{ person_id: 1, candidacy_id: 10 }]);
+		let errors = polity.perform(PolityAction::ExitPerson{ person_id: 1, successor_id: Some(1) }).unwrap_err();
+		assert_eq!(errors, vec![PolityActionError::InvalidSuccessor{ person_id: 1, successor_id: 1 }]);
+
+		let outcome = polity.perform(PolityAction::ExitPerson{ person_id: 1, successor_id: Some(2) }).unwrap();
+		assert_eq!(outcome.changes, vec![
+			PolityStateChange::SetCandidacyOwner{ candidacy_id: 10, owner_id: 2 },
+			PolityStateChange::RemoveCandidacy{ candidacy_id: 11, reason: CandidacyRemovalReason::OwnerExited },
+			PolityStateChange::RemoveCandidacy{ candidacy_id: 20, reason: CandidacyRemovalReason::OwnerExited },
+			PolityStateChange::InsertVacancy{ election_id: 1, vacancy: Vacancy{ vacated_candidacy_id: 20, vacated_at: timestamp(0) } },
+			PolityStateChange::SetResourceAllocations{ voter_id: 1, allocations: vec![] },
+			PolityStateChange::SetDelegations{ delegator_id: 3, delegations: vec![] },
+			PolityStateChange::RemovePerson{ person_id: 1 },
+		]);
+		assert_eq!(outcome.orphaned_allocations, vec![
+			OrphanedAllocation{ voter_id: 2, election_id: 1, candidacy_id: Some(20), stranded_weight: 10.into() },
+		]);
+		assert_eq!(outcome.report.vacancy_events, vec![VacancyEvent::Opened{ election_id: 1, vacated_candidacy_id: 20 }]);
+		assert_eq!(polity.candidacy(10).unwrap().owner_id, 2);
+		assert_eq!(polity.election_winners(1).count(), 0);
+		assert_eq!(polity.delegations(3), &[]);
+		assert!(!polity.state().resource_allocation_table.contains_key(&1));
+	}
+
+	#[test]
+	fn test_vacancies() {
+		let make_vacancy = |vacancy_policy: VacancyPolicy| {
+			let office = InputElection{ election_fill_method: ElectionFillMethod::Constant(100.into()), vacancy_policy, ..office_election(1) };
+			let mut polity = polity_with_document(document(vec![office]));
+			for (candidacy_id, owner_id) in [(20, 2), (21, 3)] {
+				polity.perform(PolityAction::EnterPerson{ person_id: owner_id, given_weight: 100.into() }).unwrap();
+				polity.perform(PolityAction::EnterCandidacy{ candidacy_id, owner_id, election_id: 1, content: CandidacyContent::Office{ pitch: "".into() } }).unwrap();
+			}
+			polity.perform(allocate(2, vec![(1, 20, 30)])).unwrap();
+			for _ in 0..4 { polity.perform(PolityAction::Recalculate).unwrap(); }
+			assert_eq!(polity.election_winners(1).map(|c| c.id).collect::<Vec<_>>(), vec![20]);
+			polity.perform(allocate(3, vec![(1, 21, 80)])).unwrap();
+			polity.perform(PolityAction::Recalculate).unwrap();
+			assert_eq!(polity.candidacy(21).unwrap().status, CandidacyStatus::Election(50.into()));
+
+			let outcome = polity.perform(PolityAction::ExitCandidacy{ candidacy_id: 20 }).unwrap();
+			assert_eq!(outcome.report.vacancy_events, vec![VacancyEvent::Opened{ election_id: 1, vacated_candidacy_id: 20 }]);
+			polity
+		};
+		let filled = |election_id, appointed| vec![VacancyEvent::Filled{ election_id, vacated_candidacy_id: 20, candidacy_id: 21, appointed }];
+
+		// the challenger's head start is enough to take the empty seat right away
+		let mut polity = make_vacancy(VacancyPolicy::LeaveEmpty);
+		assert_eq!(polity.perform(PolityAction::Recalculate).unwrap().report.vacancy_events, filled(1, false));
+		assert_eq!(polity.election_winners(1).map(|c| c.id).collect::<Vec<_>>(), vec![21]);
+		assert!(polity.state().vacancy_table.is_empty());
+
+		let mut polity = make_vacancy(VacancyPolicy::RequireFullBucketFill);
+		assert_eq!(polity.candidacy(21).unwrap().status, CandidacyStatus::Election(0.into()));
+		assert_eq!(polity.perform(PolityAction::Recalculate).unwrap().report.vacancy_events, vec![]);
+		assert_eq!(polity.election_winners(1).count(), 0);
+		assert_eq!(polity.perform(PolityAction::Recalculate).unwrap().report.vacancy_events, filled(1, false));
+
+		let mut polity = make_vacancy(VacancyPolicy::AppointRunnerUp);
+		assert_eq!(polity.perform(PolityAction::Recalculate).unwrap().report.vacancy_events, filled(1, true));
+		assert_eq!(polity.election_winners(1).map(|c| c.id).collect::<Vec<_>>(), vec![21]);
+	}
+
+	#[test]
+	fn test_carried_over_sub_elections() {
+		let sub_election = |id: usize, title: &str, kind: ElectionKind, carries_over: bool| InputElection{ title: title.into(), kind, carries_over, ..office_election(id) };
+		let mut polity = polity_with_document(document(vec![sub_election(1, "treasurer", ElectionKind::Office, false), sub_election(2, "clerk", ElectionKind::Office, false)]));
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 20, owner_id: 1, election_id: 1, content: CandidacyContent::Office{ pitch: "".into() } }).unwrap();
+		polity.perform(allocate(1, vec![(0, 10, 50), (1, 20, 10)])).unwrap();
+		polity.perform(PolityAction::Recalculate).unwrap();
+		assert_eq!(polity.candidacy(20).unwrap().status, CandidacyStatus::Winner);
+
+		let content = document(vec![sub_election(2, "", ElectionKind::Document, true), sub_election(9, "", ElectionKind::Office, true)]);
+		let errors = polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 12, owner_id: 1, election_id: 0, content }).unwrap_err();
+		assert_eq!(errors, vec![
+			PolityActionError::InvalidCarryOver{ candidacy_id: 12, election_id: 2 },
+			PolityActionError::InvalidCarryOver{ candidacy_id: 12, election_id: 9 },
+		]);
+
+		// the amendment keeps the treasurer, drops the clerk and adds a secretary
+		let content = document(vec![sub_election(1, "head treasurer", ElectionKind::Office, true), sub_election(3, "secretary", ElectionKind::Office, false)]);
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 11, owner_id: 1, election_id: 0, content }).unwrap();
+		polity.perform(allocate(1, vec![(0, 11, 60), (1, 20, 10)])).unwrap();
+		polity.perform(PolityAction::Recalculate).unwrap();
+		let outcome = polity.perform(PolityAction::Recalculate).unwrap();
+		assert_eq!(polity.election_winners(0).map(|c| c.id).collect::<Vec<_>>(), vec![11]);
+		assert_eq!(outcome.orphaned_allocations, vec![]);
+
+		let treasurer = polity.election(1).unwrap();
+		assert_eq!((treasurer.title.as_str(), treasurer.defining_document_id), ("head treasurer", Some(11)));
+		assert_eq!(polity.candidacy(20).unwrap().status, CandidacyStatus::Winner);
+		assert!(polity.election(2).is_none());
+		assert!(polity.election(3).is_some());
+	}
+
+	#[test]
+	fn test_carry_over_keeps_seats() {
+		let council = |seats: usize, carries_over: bool| InputElection{ seats, carries_over, ..office_election(1) };
+		let mut polity = polity_with_document(document(vec![council(2, false)]));
+		for candidacy_id in [20, 21] {
+			polity.perform(PolityAction::EnterCandidacy{ candidacy_id, owner_id: 1, election_id: 1, content: CandidacyContent::Office{ pitch: "".into() } }).unwrap();
+		}
+		polity.perform(allocate(1, vec![(0, 10, 50), (1, 20, 10), (1, 21, 10)])).unwrap();
+		polity.perform(PolityAction::Recalculate).unwrap();
+		assert_eq!(polity.election_winners(1).map(|c| c.id).collect::<HashSet<_>>(), HashSet::from([20, 21]));
+
+		// shrinking the council would leave one of its winners without a seat
+		let errors = polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 11, owner_id: 1, election_id: 0, content: document(vec![council(1, true)]) }).unwrap_err();
+		assert_eq!(errors, vec![PolityActionError::InvalidCarryOver{ candidacy_id: 11, election_id: 1 }]);
+
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 11, owner_id: 1, election_id: 0, content: document(vec![council(3, true)]) }).unwrap();
+		polity.perform(allocate(1, vec![(0, 11, 50), (1, 20, 10), (1, 21, 10)])).unwrap();
+		for _ in 0..2 { polity.perform(PolityAction::Recalculate).unwrap(); }
+		assert_eq!(polity.election(1).map(|election| (election.seats, election.defining_document_id)), Some((3, Some(11))));
+		assert_eq!(polity.election_winners(1).map(|c| c.id).collect::<HashSet<_>>(), HashSet::from([20, 21]));
+
+		// amendments carry their changed elections over, so they can't shrink them either
+		let patch = |seats: usize| DocumentPatch{ changed_elections: vec![council(seats, true)], ..DocumentPatch::default() };
+		let amendment = |patch| CandidacyContent::Amendment{ pitch: "".into(), amended_document_id: 11, patch };
+		let errors = polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 12, owner_id: 1, election_id: 0, content: amendment(patch(2)) }).unwrap_err();
+		assert_eq!(errors, vec![PolityActionError::InvalidCarryOver{ candidacy_id: 12, election_id: 1 }]);
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 12, owner_id: 1, election_id: 0, content: amendment(patch(3)) }).unwrap();
+	}
+
+	#[test]
+	fn test_amendments() {
+		let sub_election = |id: usize, title: &str| InputElection{ title: title.into(), ..office_election(id) };
+		let amendment = |amended_document_id: usize, patch: DocumentPatch| CandidacyContent::Amendment{ pitch: "".into(), amended_document_id, patch };
+
+		let mut polity = polity_with_document(document_with_body("the council meets monthly", vec![sub_election(1, "treasurer"), sub_election(2, "clerk")]));
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 20, owner_id: 1, election_id: 1, content: CandidacyContent::Office{ pitch: "".into() } }).unwrap();
+		polity.perform(allocate(1, vec![(0, 10, 50), (1, 20, 10)])).unwrap();
+		polity.perform(PolityAction::Recalculate).unwrap();
+
+		let invalid = DocumentPatch{
+			body_edits: vec![TextEdit{ start: 20, end: 30, replacement: "".into() }],
+			removed_election_ids: vec![7],
+			changed_elections: vec![],
+			added_elections: vec![sub_election(1, "")],
+		};
+		let errors = polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 12, owner_id: 1, election_id: 0, content: amendment(10, invalid) }).unwrap_err();
+		assert_eq!(errors, vec![
+			PolityActionError::InvalidAmendment{ candidacy_id: 12, error: AmendmentError::InvalidEdit{ edit_index: 0 } },
+			PolityActionError::InvalidAmendment{ candidacy_id: 12, error: AmendmentError::UnknownElection{ election_id: 7 } },
+			PolityActionError::InvalidAmendment{ candidacy_id: 12, error: AmendmentError::DuplicateElection{ election_id: 1 } },
+		]);
+		let errors = polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 12, owner_id: 1, election_id: 0, content: amendment(20, DocumentPatch::default()) }).unwrap_err();
+		assert_eq!(errors, vec![PolityActionError::InvalidAmendment{ candidacy_id: 12, error: AmendmentError::NotCurrentWinner{ amended_document_id: 20 } }]);
+
+		// changed and added elections get the same seat and update frequency checks as a document's own sub elections
+		let negative = Duration::seconds(-5);
+		let invalid_elections = |changed_elections: Vec<InputElection>, added_elections: Vec<InputElection>| DocumentPatch{
+			body_edits: vec![], removed_election_ids: vec![], changed_elections, added_elections,
+		};
+		let errors = polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 12, owner_id: 1, election_id: 0, content: amendment(10, invalid_elections(
+			vec![], vec![InputElection{ seats: 0, ..sub_election(3, "") }],
+		)) }).unwrap_err();
+		assert_eq!(errors, vec![PolityActionError::InvalidSeats{ candidacy_id: 12, election_id: 3, seats: 0 }]);
+		let errors = polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 12, owner_id: 1, election_id: 0, content: amendment(10, invalid_elections(
+			vec![], vec![InputElection{ kind: ElectionKind::Document, seats: 3, ..sub_election(3, "") }],
+		)) }).unwrap_err();
+		assert_eq!(errors, vec![PolityActionError::InvalidSeats{ candidacy_id: 12, election_id: 3, seats: 3 }]);
+		let errors = polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 12, owner_id: 1, election_id: 0, content: amendment(10, invalid_elections(
+			vec![InputElection{ update_frequency: Some(negative), ..sub_election(1, "") }], vec![],
+		)) }).unwrap_err();
+		assert_eq!(errors, vec![PolityActionError::InvalidUpdateFrequency{ candidacy_id: 12, election_id: 1, update_frequency: negative }]);
+		let errors = polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 12, owner_id: 1, election_id: 0, content: amendment(10, invalid_elections(
+			vec![], vec![InputElection{ update_frequency: Some(negative), ..sub_election(3, "") }],
+		)) }).unwrap_err();
+		assert_eq!(errors, vec![PolityActionError::InvalidUpdateFrequency{ candidacy_id: 12, election_id: 3, update_frequency: negative }]);
+		let errors = polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 12, owner_id: 1, election_id: 0, content: amendment(10, invalid_elections(
+			vec![InputElection{ seats: 0, ..sub_election(1, "") }], vec![],
+		)) }).unwrap_err();
+		assert_eq!(errors, vec![
+			PolityActionError::InvalidSeats{ candidacy_id: 12, election_id: 1, seats: 0 },
+			PolityActionError::InvalidCarryOver{ candidacy_id: 12, election_id: 1 },
+		]);
+
+		let patch = DocumentPatch{
+			body_edits: vec![TextEdit{ start: 18, end: 25, replacement: "weekly".into() }],
+			removed_election_ids: vec![2],
+			changed_elections: vec![sub_election(1, "head treasurer")],
+			added_elections: vec![sub_election(3, "secretary")],
+		};
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 11, owner_id: 1, election_id: 0, content: amendment(10, patch) }).unwrap();
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 13, owner_id: 1, election_id: 0, content: amendment(10, DocumentPatch::default()) }).unwrap();
+		let expected = document_with_body(
+			"the council meets weekly",
+			vec![InputElection{ carries_over: true, ..sub_election(1, "head treasurer") }, sub_election(3, "secretary")],
+		);
+		assert_eq!(polity.materialized_amendment(11), Some(expected.clone()));
+
+		polity.perform(allocate(1, vec![(0, 11, 60), (1, 20, 10)])).unwrap();
+		polity.perform(PolityAction::Recalculate).unwrap();
+		let outcome = polity.perform(PolityAction::Recalculate).unwrap();
+		assert!(outcome.changes.contains(&PolityStateChange::RemoveCandidacy{ candidacy_id: 13, reason: CandidacyRemovalReason::AmendedDocumentReplaced }));
+		assert_eq!(polity.election_winners(0).map(|c| c.id).collect::<Vec<_>>(), vec![11]);
+		assert_eq!(polity.candidacy(11).unwrap().content, expected);
+		assert_eq!(polity.election(1).unwrap().title, "head treasurer");
+		assert_eq!(polity.candidacy(20).unwrap().status, CandidacyStatus::Winner);
+		assert!(polity.election(2).is_none());
+		assert!(polity.election(3).is_some());
+	}
+
+	#[test]
+	fn test_update_frequency() {
+		let mut polity = Polity::new(PolityState::build().with_resource().with_update_frequency(Duration::days(7)).finish());
+		polity.perform(PolityAction::EnterPerson{ person_id: 1, given_weight: 100.into() }).unwrap();
+		let sub_election = |id: usize, update_frequency: Duration| InputElection{ update_frequency: Some(update_frequency), ..office_election(id) };
+
+		let errors = polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content: document(vec![sub_election(1, Duration::zero())]) }).unwrap_err();
+		assert_eq!(errors, vec![PolityActionError::InvalidUpdateFrequency{ candidacy_id: 10, election_id: 1, update_frequency: Duration::zero() }]);
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content: document(vec![sub_election(1, Duration::days(2))]) }).unwrap();
+		polity.perform(allocate(1, vec![(0, 10, 50)])).unwrap();
+
+		let mut recalculate_due = |day: i64| polity.perform_at(timestamp(day * 86400), PolityAction::RecalculateDue).unwrap().report.recalculated_election_ids;
+		assert_eq!(recalculate_due(0), vec![0]);
+		assert_eq!(recalculate_due(3), Vec::<usize>::new());
+		assert_eq!(recalculate_due(7), vec![0]);
+
+		assert_eq!(polity.election_winners(0).map(|c| c.id).collect::<Vec<_>>(), vec![10]);
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 20, owner_id: 1, election_id: 1, content: CandidacyContent::Office{ pitch: "".into() } }).unwrap();
+		let mut recalculate_due = |day: i64| polity.perform_at(timestamp(day * 86400), PolityAction::RecalculateDue).unwrap().report.recalculated_election_ids;
+		assert_eq!(recalculate_due(8), vec![1]);
+		assert_eq!(recalculate_due(9), Vec::<usize>::new());
+		assert_eq!(recalculate_due(10), vec![1]);
+
+		let outcome = polity.perform_at(timestamp(11 * 86400), PolityAction::Recalculate).unwrap();
+		assert_eq!(outcome.report.recalculated_election_ids, vec![0, 1]);
+		assert!(outcome.changes.contains(&PolityStateChange::SetLastRecalculated{ election_id: 0, recalculated_at: timestamp(11 * 86400) }));
+	}
+
+	#[test]
+	fn test_incremental_recalculation() {
+		let office = CandidacyContent::Office{ pitch: "".into() };
+		let allocation = |election_id: usize, candidacy_id: usize, weight: i64, fallback_candidacy_ids: Vec<usize>| ResourceAllocation{
+			election_id, candidacy_id, weight: weight.into(), fallback_candidacy_ids,
+		};
+		let actions = vec![
+			PolityAction::EnterPerson{ person_id: 1, given_weight: 100.into() },
+			PolityAction::EnterPerson{ person_id: 2, given_weight: 100.into() },
+			PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content: document(vec![office_election(1), office_election(2)]) },
+			allocate(1, vec![(0, 10, 50)]),
+			PolityAction::Recalculate,
+			PolityAction::Recalculate,
+			PolityAction::EnterCandidacy{ candidacy_id: 20, owner_id: 1, election_id: 1, content: office.clone() },
+			PolityAction::EnterCandidacy{ candidacy_id: 21, owner_id: 1, election_id: 1, content: office.clone() },
+			PolityAction::EnterCandidacy{ candidacy_id: 30, owner_id: 2, election_id: 2, content: office },
+			PolityAction::SetAllocations{ voter_id: 1, resource_allocations: vec![
+				allocation(0, 10, 50, vec![]), allocation(1, 20, 20, vec![21]),
+			], resource_score_allocations: vec![] },
+			allocate(2, vec![(2, 30, 5)]),
+			PolityAction::Recalculate,
+			PolityAction::Recalculate,
+			allocate(2, vec![(2, 30, 15)]),
+			PolityAction::Recalculate,
+			// the fallback moves weight into the clean candidacy 21
+			PolityAction::ExitCandidacy{ candidacy_id: 20 },
+			PolityAction::Recalculate,
+			PolityAction::Recalculate,
+			PolityAction::ExitPerson{ person_id: 2, successor_id: None },
+			PolityAction::Recalculate,
+		];
+
+		let mut incremental = assert_same_as_full_recalculation(actions);
+		assert_eq!(incremental.election_winners(1).map(|c| c.id).collect::<Vec<_>>(), vec![21]);
+
+		// nothing has changed since the last recalculation, so nothing is recalculated
+		assert_eq!(incremental.state().dirty_election_ids, HashSet::new());
+		let outcome = incremental.perform(PolityAction::Recalculate).unwrap();
+		assert_eq!(outcome.report.recalculated_election_ids, vec![0, 1]);
+		assert!(outcome.changes.iter().all(|change| matches!(change, PolityStateChange::SetLastRecalculated{..})));
+
+		incremental.perform(allocate(1, vec![(0, 10, 50), (1, 21, 30)])).unwrap();
+		assert_eq!(incremental.state().dirty_election_ids, HashSet::from([1]));
+	}
+
+	// performs the actions on one polity that recalculates incrementally and another that recalculates every election every time
+	// checking after every action that they agree, and returns the incremental one
+	fn assert_same_as_full_recalculation(actions: Vec<PolityAction>) -> Polity {
+		let mut incremental = Polity::new(PolityState::build().with_resource().finish());
+		let mut full = Polity::new(PolityState::build().with_resource().finish());
+		for action in actions {
+			if matches!(action, PolityAction::Recalculate) {
+				let mut state = full.into_state();
+				state.dirty_election_ids = state.election_table.iter().map(|election| election.id).collect();
+				full = Polity::new(state);
+			}
+			let incremental_report = incremental.perform(action.clone()).unwrap().report;
+			let full_report = full.perform(action).unwrap().report;
+			assert_eq!(incremental_report, full_report);
+
+			let mut incremental_state = incremental.state().clone();
+			let mut full_state = full.state().clone();
+			incremental_state.dirty_election_ids.clear();
+			full_state.dirty_election_ids.clear();
+			assert_eq!(incremental_state, full_state);
+
+			// the indexes kept up to date change by change are the same as ones built from scratch
+			let mut rebuilt_state = incremental_state.clone();
+			rebuilt_state.rebuild_indexes();
+			assert_eq!(rebuilt_state, incremental_state);
+		}
+		incremental
+	}
+
+	#[test]
+	fn test_incremental_recalculation_after_carry_over() {
+		let office = CandidacyContent::Office{ pitch: "".into() };
+		let bylaws = |carries_over| InputElection{ kind: ElectionKind::Document, carries_over, ..office_election(1) };
+		let actions = vec![
+			PolityAction::EnterPerson{ person_id: 1, given_weight: 100.into() },
+			PolityAction::EnterPerson{ person_id: 2, given_weight: 100.into() },
+			PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content: document(vec![bylaws(false)]) },
+			allocate(1, vec![(0, 10, 50)]),
+			PolityAction::Recalculate,
+			PolityAction::Recalculate,
+			PolityAction::EnterCandidacy{ candidacy_id: 30, owner_id: 1, election_id: 1, content: document(vec![office_election(3)]) },
+			allocate(1, vec![(0, 10, 50), (1, 30, 10)]),
+			PolityAction::Recalculate,
+			PolityAction::EnterCandidacy{ candidacy_id: 41, owner_id: 2, election_id: 3, content: office.clone() },
+			allocate(2, vec![(3, 41, 10)]),
+			PolityAction::Recalculate,
+			// the challenger is exactly as strong as the winner, so election 3 settles
+			PolityAction::EnterCandidacy{ candidacy_id: 40, owner_id: 1, election_id: 3, content: office },
+			allocate(1, vec![(0, 10, 50), (1, 30, 10), (3, 40, 10)]),
+			PolityAction::Recalculate,
+			PolityAction::Recalculate,
+			// the delegation only covers election 3 once the new constitution carries election 1 over
+			PolityAction::EnterCandidacy{ candidacy_id: 11, owner_id: 1, election_id: 0, content: document(vec![bylaws(true)]) },
+			PolityAction::SetDelegations{ delegator_id: 2, delegations: vec![
+				Delegation{ delegate_id: 1, weight: 50.into(), scope: DelegationScope::UnderDocument(11) },
+			] },
+			PolityAction::Recalculate,
+			allocate(1, vec![(0, 11, 50), (1, 30, 10), (3, 40, 10)]),
+			PolityAction::Recalculate,
+			PolityAction::Recalculate,
+			PolityAction::Recalculate,
+		];
+
+		let polity = assert_same_as_full_recalculation(actions);
+		assert_eq!(polity.election(1).unwrap().defining_document_id, Some(11));
+		assert_eq!(polity.election(3).unwrap().defining_document_id, Some(30));
+		assert_eq!(polity.candidacy(40).unwrap().status, CandidacyStatus::Election(5.into()));
+	}
+
+	#[test]
+	fn test_resource_score_averaging() {
+		let score_allocation = |approve_weight: i64, disapprove_weight: i64, scores: Vec<(usize, i64)>| ResourceScoreAllocation{
+			election_id: 0, approve_weight: approve_weight.into(), disapprove_weight: disapprove_weight.into(),
+			scores: scores.into_iter().map(|(candidacy_id, score)| (candidacy_id, score.into())).collect(),
+			fallback_candidacy_ids: HashMap::new(),
+		};
+		let allocations = [
+			score_allocation(4, 0, vec![(1, 1), (2, 1)]),
+			score_allocation(6, 2, vec![(1, 1), (2, -1)]),
+			// only this voter has seen candidacy 3
+			score_allocation(9, 0, vec![(3, 1)]),
+		];
+		let allocations: Vec<_> = allocations.iter().collect();
+
+		assert_eq!(aggregate_resource_score_votes(&allocations, false), HashMap::from([
+			(1, 10.into()), (2, 2.into()), (3, 9.into()),
+		]));
+		assert_eq!(aggregate_resource_score_votes(&allocations, true), HashMap::from([
+			(1, 1.into()), (2, "0.3333333333333333333333333333".parse().unwrap()), (3, 1.into()),
+		]));
+		let rounded = |aggregation: HashMap<usize, Weight>| -> HashMap<usize, Weight> {
+			aggregation.into_iter().map(|(candidacy_id, vote)| (candidacy_id, vote.round_dp(20))).collect()
+		};
+		let root_two = Weight::TWO.sqrt().unwrap();
+		assert_eq!(rounded(aggregate_quadratic_resource_score_votes(&allocations, true)), rounded(HashMap::from([
+			(1, 1.into()), (2, (Weight::TWO - root_two) / (Weight::TWO + root_two)), (3, 1.into()),
+		])));
+	}
+
+	#[test]
+	fn test_resource_score_averaging_fill_timing() {
+		// averaged totals are scores, so buckets fill by the score and not by the weight behind it
+		let statuses = |use_averaging: bool| {
+			let sub_election = InputElection{
+				selection_method: SelectionMethod::ResourceScore{ scale_quadratically: false, use_averaging },
+				election_fill_method: ElectionFillMethod::Constant(3.into()),
+				..office_election(1)
+			};
+			let mut polity = polity_with_document(document(vec![sub_election]));
+
+			polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 20, owner_id: 1, election_id: 1, content: CandidacyContent::Office{ pitch: "".into() } }).unwrap();
+			polity.perform(PolityAction::SetAllocations{ voter_id: 1, resource_allocations: vec![
+				ResourceAllocation{ election_id: 0, candidacy_id: 10, weight: 50.into(), fallback_candidacy_ids: vec![] },
+			], resource_score_allocations: vec![
+				ResourceScoreAllocation{
+					election_id: 1, approve_weight: 50.into(), disapprove_weight: 0.into(),
+					scores: HashMap::from([(20, 1.into())]), fallback_candidacy_ids: HashMap::new(),
+				},
+			] }).unwrap();
+			(0..3)
+				.map(|_| {
+					polity.perform(PolityAction::Recalculate).unwrap();
+					polity.candidacy(20).unwrap().status
+				})
+				.collect::<Vec<_>>()
+		};
+
+		assert_eq!(statuses(false), vec![CandidacyStatus::Winner; 3]);
+		assert_eq!(statuses(true), vec![
+			CandidacyStatus::Election(1.into()), CandidacyStatus::Election(2.into()), CandidacyStatus::Winner,
+		]);
+	}
+
+	#[test]
+	fn test_negative_buckets() {
+		let entries = || vec![
+			entry(1, true, 0, -6), entry(2, true, -6, -6), entry(3, true, -6, 2),
+			entry(4, false, 0, -15), entry(5, false, -15, -15), entry(6, false, -15, 4), entry(7, false, 5, 2),
+		];
+
+		let NextStatuses{ statuses, removed_candidacy_ids: removed, .. } = calculate_next_statuses(1, NegativeBucketsKind::None, vec![(9, 5.into())], entries());
+		assert_eq!(removed, Vec::<usize>::new());
+		assert_eq!(statuses.get(&2), Some(&CandidacyStatus::Nomination(0.into())));
+		assert_eq!(statuses.get(&5), Some(&CandidacyStatus::Election(0.into())));
+
+		let NextStatuses{ statuses, removed_candidacy_ids: removed, .. } = calculate_next_statuses(1, NegativeBucketsKind::WithoutRemoval, vec![(9, 5.into())], entries());
+		assert_eq!(removed, Vec::<usize>::new());
+		assert_eq!(statuses, HashMap::from([
+			(1, CandidacyStatus::Nomination((-6).into())),
+			(2, CandidacyStatus::Nomination((-10).into())),
+			(3, CandidacyStatus::Nomination((-4).into())),
+			(4, CandidacyStatus::Election((-15).into())),
+			(5, CandidacyStatus::Election((-20).into())),
+			(6, CandidacyStatus::Election((-11).into())),
+			// support below the winner doesn't push a non-negative bucket below zero
+			(7, CandidacyStatus::Election(2.into())),
+			(9, CandidacyStatus::Winner),
+		]));
+
+		let NextStatuses{ statuses, removed_candidacy_ids: removed, .. } = calculate_next_statuses(1, NegativeBucketsKind::WithRemoval, vec![(9, 5.into())], entries());
+		assert_eq!(removed, vec![2, 5]);
+		assert_eq!(statuses.get(&2), None);
+		assert_eq!(statuses.get(&1), Some(&CandidacyStatus::Nomination((-6).into())));
+		assert_eq!(statuses.get(&4), Some(&CandidacyStatus::Election((-15).into())));
+
+		let mut polity = Polity::new(PolityState::build().with_resource().finish());
+		polity.perform(PolityAction::EnterPerson{ person_id: 1, given_weight: 10.into() }).unwrap();
+		let sub_election = InputElection{
+			selection_method: SelectionMethod::ResourceScore{ scale_quadratically: false, use_averaging: false },
+			election_fill_method: ElectionFillMethod::Constant(15.into()), negative_buckets: NegativeBucketsKind::WithRemoval,
+			..office_election(1)
+		};
+		let content = document(vec![sub_election]);
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content }).unwrap();
+		polity.perform(allocate(1, vec![(0, 10, 10)])).unwrap();
+		for _ in 0..10 { polity.perform(PolityAction::Recalculate).unwrap(); }
+
+		let content = CandidacyContent::Office{ pitch: "".into() };
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 20, owner_id: 1, election_id: 1, content }).unwrap();
+		// a negative weight would let the voter spend more than their given weight elsewhere
+		let errors = polity.perform(allocate(1, vec![(0, 10, -10)])).unwrap_err();
+		assert_eq!(errors, vec![PolityActionError::InvalidAllocationWeight{ voter_id: 1, election_id: 0, weight: (-10).into() }]);
+
+		polity.perform(PolityAction::SetAllocations{ voter_id: 1, resource_allocations: vec![], resource_score_allocations: vec![
+			ResourceScoreAllocation{
+				election_id: 1, approve_weight: 0.into(), disapprove_weight: 10.into(),
+				scores: HashMap::from([(20, (-1).into())]), fallback_candidacy_ids: HashMap::new(),
+			},
+		] }).unwrap();
+		polity.perform(PolityAction::Recalculate).unwrap();
+		assert_eq!(polity.candidacy(20).unwrap().status, CandidacyStatus::Election((-10).into()));
+		let outcome = polity.perform(PolityAction::Recalculate).unwrap();
+		assert!(outcome.changes.contains(&PolityStateChange::RemoveCandidacy{ candidacy_id: 20, reason: CandidacyRemovalReason::NegativeBucket }));
+		assert_eq!(polity.candidacy(20), None);
+	}
+
+	#[test]
+	fn test_electorate_size_fill_requirements() {
+		let method = ElectionFillMethod::OnlyElectorateSize(2.into());
+		assert_eq!(calculate_election_fill_requirement(method, 20, 20), 40.into());
+		assert_eq!(calculate_election_fill_requirement(method, 200_000, 3), 400_000.into());
+
+		let method = ElectionFillMethod::ElectorateSizeWithWideness(2.into());
+		assert_eq!(calculate_election_fill_requirement(method, 20, 0), 40.into());
+		assert_eq!(calculate_election_fill_requirement(method, 20, 10), 30.into());
+		assert_eq!(calculate_election_fill_requirement(method, 20, 20), 20.into());
+		assert_eq!(calculate_election_fill_requirement(method, 0, 0), 0.into());
+	}
+
+	#[test]
+	fn test_noise_adaptive_nomination() {
+		let churn = calculate_next_churn(None, HashMap::from([(1, 10.into()), (2, (-4).into())]));
+		assert_eq!(churn.churn, 7.into());
+		let churn = calculate_next_churn(Some(&churn), HashMap::from([(1, 10.into()), (2, (-4).into())]));
+		assert_eq!(churn.churn, "3.5".parse().unwrap());
+		// candidacy 2 disappeared and candidacy 3 appeared
+		let churn = calculate_next_churn(Some(&churn), HashMap::from([(1, 8.into()), (3, 1.into())]));
+		assert_eq!(churn.churn, "5.25".parse().unwrap());
+		assert_eq!(churn.previous_totals, HashMap::from([(1, 8.into()), (3, 1.into())]));
+
+		// the halves only survive rounding if they're added together before the large movement
+		let large: Weight = "10000000000000000000000000000".parse().unwrap();
+		let rounded = calculate_next_churn(None, HashMap::from([(1, "0.5".parse().unwrap()), (2, "0.5".parse().unwrap()), (3, large)]));
+		assert_eq!(rounded.churn, (large + Weight::ONE) / Weight::TWO);
+
+		let method = NominationFillMethod::NoiseAdaptive{ base: 10.into(), churn_multiplier: 2.into() };
+		assert_eq!(calculate_nomination_fill_requirement(method, None), 10.into());
+		assert_eq!(calculate_nomination_fill_requirement(method, Some(&churn)), "20.5".parse().unwrap());
+
+		let mut polity = Polity::new(PolityState::build().with_resource().finish());
+		polity.perform(PolityAction::EnterPerson{ person_id: 1, given_weight: 10.into() }).unwrap();
+		polity.perform(PolityAction::EnterPerson{ person_id: 2, given_weight: 10.into() }).unwrap();
+		let sub_election = InputElection{ nomination_fill_method: method, ..office_election(1) };
+		let content = document(vec![sub_election]);
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content }).unwrap();
+		polity.perform(allocate(1, vec![(0, 10, 10)])).unwrap();
+		polity.perform(allocate(2, vec![(0, 10, 10)])).unwrap();
+		for _ in 0..5 { polity.perform(PolityAction::Recalculate).unwrap(); }
+		assert!(polity.election(1).is_some());
+
+		let content = CandidacyContent::Office{ pitch: "".into() };
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 20, owner_id: 1, election_id: 1, content: content.clone() }).unwrap();
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 21, owner_id: 1, election_id: 1, content }).unwrap();
+
+		// voter 2 flips between the candidacies every time, so the requirement climbs while 20 only gets a steady 4
+		polity.perform(allocate(1, vec![(1, 20, 4)])).unwrap();
+		for round in 0..4 {
+			let flipping_to = if round % 2 == 0 { 20 } else { 21 };
+			polity.perform(allocate(2, vec![(1, flipping_to, 10)])).unwrap();
+			polity.perform(PolityAction::Recalculate).unwrap();
+		}
+		assert!(polity.state().election_churn_table.get(&1).unwrap().churn > 10.into());
+		assert!(matches!(polity.candidacy(20).unwrap().status, CandidacyStatus::Nomination(_)));
+		assert!(matches!(polity.candidacy(21).unwrap().status, CandidacyStatus::Nomination(_)));
+
+		// once things calm down the churn decays and steady support gets through
+		polity.perform(PolityAction::SetAllocations{ voter_id: 2, resource_allocations: vec![], resource_score_allocations: vec![] }).unwrap();
+		for _ in 0..10 { polity.perform(PolityAction::Recalculate).unwrap(); }
+		assert!(!matches!(polity.candidacy(20).unwrap().status, CandidacyStatus::Nomination(_)));
+	}
+
+	#[test]
+	fn test_recalculation_uses_fill_methods() {
+		let mut polity = Polity::new(PolityState::build().with_resource().finish());
+		polity.perform(PolityAction::EnterPerson{ person_id: 1, given_weight: 10.into() }).unwrap();
+		let sub_election = InputElection{ nomination_fill_method: NominationFillMethod::Constant(15.into()), ..office_election(1) };
+		let content = document(vec![sub_election]);
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content }).unwrap();
+		polity.perform(allocate(1, vec![(0, 10, 10)])).unwrap();
+
+		// the root constitution requires a bucket of 100
+		for _ in 0..9 {
+			polity.perform(PolityAction::Recalculate).unwrap();
+			assert_eq!(polity.election_winners(0).count(), 0);
+		}
+		polity.perform(PolityAction::Recalculate).unwrap();
+		assert_eq!(polity.election_winners(0).map(|c| c.id).collect::<Vec<_>>(), vec![10]);
+		assert!(polity.election(1).is_some());
+
+		let content = CandidacyContent::Office{ pitch: "".into() };
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 20, owner_id: 1, election_id: 1, content }).unwrap();
+		assert_eq!(polity.candidacy(20).unwrap().status, CandidacyStatus::Nomination(0.into()));
+		polity.perform(allocate(1, vec![(1, 20, 10)])).unwrap();
+
+		polity.perform(PolityAction::Recalculate).unwrap();
+		assert_eq!(polity.candidacy(20).unwrap().status, CandidacyStatus::Nomination(10.into()));
+		polity.perform(PolityAction::Recalculate).unwrap();
+		assert_eq!(polity.candidacy(20).unwrap().status, CandidacyStatus::Election(0.into()));
+		polity.perform(PolityAction::Recalculate).unwrap();
+		assert_eq!(polity.candidacy(20).unwrap().status, CandidacyStatus::Winner);
+	}
 
 	// https://proptest-rs.github.io/proptest/proptest/tutorial/compound-strategies.html
 	// https://docs.rs/proptest/latest/proptest/index.html
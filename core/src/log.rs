@@ -0,0 +1,225 @@
+use super::*;
+
+// an append-only record of every action a polity has been asked to perform
+// the log is the source of truth, any PolityState can be rebuilt from it by replaying from a freshly built state
+// once a snapshot covers some prefix of the log that prefix can be compacted away,
+// after which positions still count from the very first entry ever appended
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PolityActionLog {
+	first_position: usize,
+	entries: Vec<PolityActionEntry>,
+}
+
+// the state produced by applying every entry before position
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PolitySnapshot {
+	pub position: usize,
+	pub state: PolityState,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReplayFailure {
+	pub position: usize,
+	pub occurred_at: DateTime,
+	pub errors: Vec<PolityActionError>,
+}
+
+#[derive(Debug)]
+pub struct Replay {
+	pub state: PolityState,
+	pub failures: Vec<ReplayFailure>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RestoreError {
+	// the entries needed to get from the requested position to the end of the log have been compacted
+	Compacted{ position: usize, first_position: usize },
+	AheadOfLog{ position: usize, end_position: usize },
+	// restoring from the snapshot didn't produce the same state as a full replay
+	Mismatch{ position: usize },
+}
+
+impl PolityActionLog {
+	pub fn new() -> PolityActionLog {
+		PolityActionLog { first_position: 0, entries: Vec::new() }
+	}
+
+	// returns the position of the appended entry
+	pub fn append(&mut self, entry: PolityActionEntry) -> usize {
+		self.entries.push(entry);
+		self.end_position() - 1
+	}
+
+	// only the entries that haven't been compacted, the first of which is at first_position
+	pub fn entries(&self) -> &[PolityActionEntry] { &self.entries }
+	pub fn first_position(&self) -> usize { self.first_position }
+	pub fn end_position(&self) -> usize { self.first_position + self.entries.len() }
+	pub fn len(&self) -> usize { self.entries.len() }
+	pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+
+	// failing entries are recorded and skipped, exactly as they would have been when first performed
+	pub fn replay(&self, initial: PolityState) -> Result<Replay, RestoreError> {
+		self.replay_from(initial, 0)
+	}
+
+	// the given state must be the result of every entry in the log, for example the state of a Polity kept alongside it
+	pub fn snapshot(&self, state: &PolityState) -> PolitySnapshot {
+		PolitySnapshot { position: self.end_position(), state: state.clone() }
+	}
+
+	pub fn snapshot_due(&self, latest: Option<&PolitySnapshot>, interval: usize) -> bool {
+		let latest_position = latest.map(|snapshot| snapshot.position).unwrap_or(0);
+		self.end_position().saturating_sub(latest_position) >= interval
+	}
+
+	pub fn restore(&self, snapshot: &PolitySnapshot) -> Result<Replay, RestoreError> {
+		self.replay_from(snapshot.state.clone(), snapshot.position)
+	}
+
+	// drops every entry the snapshot already covers, returning how many were dropped
+	pub fn compact(&mut self, snapshot: &PolitySnapshot) -> usize {
+		let position = usize::min(snapshot.position, self.end_position());
+		if position <= self.first_position { return 0; }
+
+		let dropped = position - self.first_position;
+		self.entries.drain(..dropped);
+		self.first_position = position;
+		dropped
+	}
+
+	// compares a restore from the snapshot against a replay of the whole log, so this must happen before compaction
+	pub fn verify_snapshot(&self, initial: PolityState, snapshot: &PolitySnapshot) -> Result<(), RestoreError> {
+		let full = self.replay(initial)?;
+		let restored = self.restore(snapshot)?;
+		if full.state != restored.state {
+			return Err(RestoreError::Mismatch{ position: snapshot.position });
+		}
+		Ok(())
+	}
+
+	fn replay_from(&self, initial: PolityState, position: usize) -> Result<Replay, RestoreError> {
+		if position < self.first_position {
+			return Err(RestoreError::Compacted{ position, first_position: self.first_position });
+		}
+		if position > self.end_position() {
+			return Err(RestoreError::AheadOfLog{ position, end_position: self.end_position() });
+		}
+
+		let entries = &self.entries[(position - self.first_position)..];
+		Ok(replay_entries(initial, position, entries))
+	}
+}
+
+fn replay_entries(initial: PolityState, first_position: usize, entries: &[PolityActionEntry]) -> Replay {
+	let mut polity = Polity::new(initial);
+	let mut failures = Vec::new();
+	for (offset, entry) in entries.iter().enumerate() {
+		if let Err(errors) = polity.perform_entry(entry.clone()) {
+			failures.push(ReplayFailure { position: first_position + offset, occurred_at: entry.occurred_at, errors });
+		}
+	}
+
+	Replay { state: polity.into_state(), failures }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn make_log() -> PolityActionLog {
+		let mut log = PolityActionLog::new();
+		let content = document(vec![]);
+		let actions = vec![
+			PolityAction::EnterPerson{ person_id: 1, given_weight: 100.into() },
+			PolityAction::EnterPerson{ person_id: 2, given_weight: 100.into() },
+			PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content: content.clone() },
+			PolityAction::EnterCandidacy{ candidacy_id: 11, owner_id: 2, election_id: 0, content },
+			// fails, person 3 was never entered
+			PolityAction::SetAllocations{ voter_id: 3, resource_allocations: vec![], resource_score_allocations: vec![] },
+			PolityAction::SetAllocations{ voter_id: 1, resource_allocations: vec![], resource_score_allocations: vec![
+				ResourceScoreAllocation{ election_id: 0, approve_weight: 30.into(), disapprove_weight: 0.into(), scores: HashMap::from([(10, 1.into())]), fallback_candidacy_ids: HashMap::new() },
+			] },
+			PolityAction::SetAllocations{ voter_id: 2, resource_allocations: vec![], resource_score_allocations: vec![
+				ResourceScoreAllocation{ election_id: 0, approve_weight: 70.into(), disapprove_weight: 20.into(), scores: HashMap::from([(10, 1.into()), (11, (-1).into())]), fallback_candidacy_ids: HashMap::new() },
+			] },
+			PolityAction::Recalculate,
+			PolityAction::Recalculate,
+		];
+		for (occurred_at, action) in actions.into_iter().enumerate() {
+			log.append(PolityActionEntry{ occurred_at: timestamp(occurred_at as i64), change: action });
+		}
+		log
+	}
+
+	#[test]
+	fn test_replay_is_deterministic() {
+		let log = make_log();
+		let first = log.replay(PolityState::build().finish()).unwrap();
+		for _ in 0..10 {
+			let again = log.replay(PolityState::build().finish()).unwrap();
+			assert_eq!(again.state, first.state);
+			assert_eq!(again.failures, first.failures);
+		}
+
+		let polity = Polity::new(first.state);
+		assert_eq!(polity.election_winners(0).map(|c| c.id).collect::<Vec<_>>(), vec![10]);
+	}
+
+	#[test]
+	fn test_replay_reports_failures() {
+		let log = make_log();
+		assert_eq!(log.len(), 9);
+		let replay = log.replay(PolityState::build().finish()).unwrap();
+		assert_eq!(replay.failures, vec![ReplayFailure{
+			position: 4,
+			occurred_at: timestamp(4),
+			errors: vec![PolityActionError::NotFound{ id: 3, table_kind: TableKind::StoragePerson }],
+		}]);
+	}
+
+	#[test]
+	fn test_snapshot_restore_and_compaction() {
+		let mut log = make_log();
+		let initial = PolityState::build().finish();
+
+		// take a snapshot partway through, then keep appending
+		let mut partial = log.clone();
+		partial.entries.truncate(6);
+		let snapshot = partial.snapshot(&partial.replay(initial.clone()).unwrap().state);
+		assert_eq!(snapshot.position, 6);
+		assert!(!log.snapshot_due(Some(&snapshot), 4));
+		assert!(log.snapshot_due(Some(&snapshot), 3));
+
+		log.verify_snapshot(initial.clone(), &snapshot).unwrap();
+		let full = log.replay(initial.clone()).unwrap();
+
+		assert_eq!(log.compact(&snapshot), 6);
+		assert_eq!(log.first_position(), 6);
+		assert_eq!(log.len(), 3);
+		assert_eq!(log.compact(&snapshot), 0);
+
+		let restored = log.restore(&snapshot).unwrap();
+		assert_eq!(restored.state, full.state);
+		assert_eq!(restored.failures, vec![]);
+
+		assert_eq!(log.replay(initial.clone()).unwrap_err(), RestoreError::Compacted{ position: 0, first_position: 6 });
+		assert_eq!(log.verify_snapshot(initial, &snapshot).unwrap_err(), RestoreError::Compacted{ position: 0, first_position: 6 });
+
+		let position = log.append(PolityActionEntry{ occurred_at: timestamp(9), change: PolityAction::Recalculate });
+		assert_eq!(position, 9);
+		let ahead = PolitySnapshot{ position: 11, state: full.state };
+		assert_eq!(log.restore(&ahead).unwrap_err(), RestoreError::AheadOfLog{ position: 11, end_position: 10 });
+	}
+
+	#[test]
+	fn test_verify_snapshot_mismatch() {
+		let log = make_log();
+		let initial = PolityState::build().finish();
+		let wrong = PolitySnapshot{ position: 6, state: initial.clone() };
+		assert_eq!(log.verify_snapshot(initial, &wrong).unwrap_err(), RestoreError::Mismatch{ position: 6 });
+	}
+}
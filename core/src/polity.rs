@@ -0,0 +1,304 @@
+use super::*;
+
+// the public face of the engine
+// callers hand in actions and get back either the low level changes they produced or the errors that prevented them
+// the state itself is only reachable through read-only queries, so every mutation goes through calculate_polity_action
+#[derive(Debug)]
+pub struct Polity {
+	state: PolityState,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PolityActionOutcome {
+	pub changes: Vec<PolityStateChange>,
+	// only recalculations and exits fill in the report, and nothing in it prevents the changes from being applied
+	pub report: RecalculationReport,
+	pub orphaned_allocations: Vec<OrphanedAllocation>,
+}
+
+// the errors of one action in a batch, along with its position in the batch
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BatchActionErrors {
+	pub index: usize,
+	pub errors: Vec<PolityActionError>,
+}
+
+impl Polity {
+	pub fn new(state: PolityState) -> Polity {
+		Polity { state }
+	}
+
+	pub fn state(&self) -> &PolityState { &self.state }
+	pub fn into_state(self) -> PolityState { self.state }
+
+	// validates the action against the current state without touching it
+	// an action is only accepted if it produced no errors at all, even if some of its parts were valid
+	// untimed actions are treated as happening at the same time as the latest timed one
+	pub fn calculate(&self, action: PolityAction) -> Result<PolityActionOutcome, Vec<PolityActionError>> {
+		self.calculate_at(self.state.latest_occurred_at, action)
+	}
+
+	pub fn calculate_at(&self, occurred_at: DateTime, action: PolityAction) -> Result<PolityActionOutcome, Vec<PolityActionError>> {
+		let mut errors = Vec::new();
+		let mut changes = Vec::new();
+		let mut report = RecalculationReport::default();
+		let result = calculate_polity_action(&self.state, &mut errors, &mut changes, &mut report, occurred_at, action);
+		if result.is_none() || !errors.is_empty() {
+			return Err(errors);
+		}
+		let orphaned_allocations = calculate_orphaned_allocations(&self.state, &changes);
+		Ok(PolityActionOutcome { changes, report, orphaned_allocations })
+	}
+
+	// only for outcomes calculate produced against this exact state, outside the crate perform is the only way in
+	pub(crate) fn apply(&mut self, outcome: &PolityActionOutcome) {
+		self.state.apply_changes(outcome.changes.clone());
+	}
+
+	pub fn perform(&mut self, action: PolityAction) -> Result<PolityActionOutcome, Vec<PolityActionError>> {
+		let outcome = self.calculate(action)?;
+		self.apply(&outcome);
+		Ok(outcome)
+	}
+
+	pub fn perform_at(&mut self, occurred_at: DateTime, action: PolityAction) -> Result<PolityActionOutcome, Vec<PolityActionError>> {
+		let outcome = self.calculate_at(occurred_at, action)?;
+		self.apply(&outcome);
+		Ok(outcome)
+	}
+
+	pub fn perform_entry(&mut self, entry: PolityActionEntry) -> Result<PolityActionOutcome, Vec<PolityActionError>> {
+		self.perform_at(entry.occurred_at, entry.change)
+	}
+
+	// validates every action against the state as the earlier actions in the batch left it, all happening at the same time
+	// a batch is only accepted if every action in it is, and failed actions are skipped so the ones after them are still checked
+	pub fn calculate_batch(&self, actions: Vec<PolityAction>) -> Result<Vec<PolityActionOutcome>, Vec<BatchActionErrors>> {
+		self.calculate_batch_at(self.state.latest_occurred_at, actions)
+	}
+
+	pub fn calculate_batch_at(&self, occurred_at: DateTime, actions: Vec<PolityAction>) -> Result<Vec<PolityActionOutcome>, Vec<BatchActionErrors>> {
+		self.run_batch(occurred_at, actions).map(|(_, outcomes)| outcomes)
+	}
+
+	pub fn perform_batch(&mut self, actions: Vec<PolityAction>) -> Result<Vec<PolityActionOutcome>, Vec<BatchActionErrors>> {
+		self.perform_batch_at(self.state.latest_occurred_at, actions)
+	}
+
+	pub fn perform_batch_at(&mut self, occurred_at: DateTime, actions: Vec<PolityAction>) -> Result<Vec<PolityActionOutcome>, Vec<BatchActionErrors>> {
+		let (state, outcomes) = self.run_batch(occurred_at, actions)?;
+		self.state = state;
+		Ok(outcomes)
+	}
+
+	// the batch runs against a copy of the state, which is only kept if every action succeeded
+	fn run_batch(&self, occurred_at: DateTime, actions: Vec<PolityAction>) -> Result<(PolityState, Vec<PolityActionOutcome>), Vec<BatchActionErrors>> {
+		let mut scratch = Polity::new(self.state.clone());
+		let mut outcomes = Vec::new();
+		let mut batch_errors = Vec::new();
+		for (index, action) in actions.into_iter().enumerate() {
+			match scratch.perform_at(occurred_at, action) {
+				Ok(outcome) => { outcomes.push(outcome); },
+				Err(errors) => { batch_errors.push(BatchActionErrors { index, errors }); },
+			}
+		}
+
+		if !batch_errors.is_empty() {
+			return Err(batch_errors);
+		}
+		Ok((scratch.into_state(), outcomes))
+	}
+
+	// what the next Recalculate would do if the hypothetical allocations were set first, without changing anything
+	pub fn preview_recalculation(&self, hypothetical_allocations: Vec<PolityAction>) -> Result<RecalculationPreview, Vec<BatchActionErrors>> {
+		preview_recalculation(&self.state, hypothetical_allocations)
+	}
+
+	pub fn person(&self, person_id: usize) -> Option<&StoragePerson> {
+		self.state.person_table.get(&person_id)
+	}
+	pub fn persons(&self) -> impl Iterator<Item = &StoragePerson> {
+		self.state.person_table.iter()
+	}
+
+	pub fn delegations(&self, delegator_id: usize) -> &[Delegation] {
+		self.state.delegation_table.get(&delegator_id).map(|delegations| delegations.as_slice()).unwrap_or(&[])
+	}
+
+	pub fn election(&self, election_id: usize) -> Option<&StorageElection> {
+		self.state.election_table.get(&election_id)
+	}
+	pub fn elections(&self) -> impl Iterator<Item = &StorageElection> {
+		self.state.election_table.iter()
+	}
+	pub fn election_churn(&self, election_id: usize) -> Option<&ElectionChurn> {
+		self.state.election_churn_table.get(&election_id)
+	}
+	pub fn elections_under_document(&self, document_id: usize) -> impl Iterator<Item = &StorageElection> {
+		self.state.election_table.iter().filter(move |e| e.defining_document_id == Some(document_id))
+	}
+
+	pub fn enactments(&self, election_id: usize) -> &[Enactment] {
+		self.state.enactment_table.get(&election_id).map(|enactments| enactments.as_slice()).unwrap_or(&[])
+	}
+
+	// the tree of documents that were in force under this document election at the given time
+	// each document's sub elections are followed through the document as it was enacted, even if those elections have since been removed
+	pub fn constitution_at(&self, election_id: usize, at: DateTime) -> Option<ConstitutionNode> {
+		let enactment = self.enactments(election_id).iter().find(|enactment| enactment.in_force_at(at))?;
+		let children = match &enactment.content {
+			CandidacyContent::Document{ sub_elections, .. } => sub_elections.iter()
+				.filter(|sub_election| sub_election.kind == ElectionKind::Document)
+				.filter_map(|sub_election| self.constitution_at(sub_election.id, at))
+				.collect(),
+			_ => Vec::new(),
+		};
+		Some(ConstitutionNode { election_id, enactment: enactment.clone(), children })
+	}
+
+	pub fn candidacy(&self, candidacy_id: usize) -> Option<&StorageCandidacy> {
+		self.state.candidacy_table.get(&candidacy_id)
+	}
+	pub fn candidacies(&self) -> impl Iterator<Item = &StorageCandidacy> {
+		self.state.candidacy_table.iter()
+	}
+	pub fn election_candidacies(&self, election_id: usize) -> impl Iterator<Item = &StorageCandidacy> {
+		self.state.candidacy_table.iter().filter(move |c| c.election_id == election_id)
+	}
+	// what an amendment would become if it won right now
+	pub fn materialized_amendment(&self, candidacy_id: usize) -> Option<CandidacyContent> {
+		match &self.candidacy(candidacy_id)?.content {
+			CandidacyContent::Amendment{ pitch, amended_document_id, patch } => {
+				materialize_amendment(&self.candidacy(*amended_document_id)?.content, pitch, patch)
+			},
+			_ => None,
+		}
+	}
+	pub fn election_winners(&self, election_id: usize) -> impl Iterator<Item = &StorageCandidacy> {
+		self.election_candidacies(election_id).filter(|c| c.status == CandidacyStatus::Winner)
+	}
+}
+
+impl From<PolityState> for Polity {
+	fn from(state: PolityState) -> Polity { Polity::new(state) }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_perform_and_query() {
+		let mut polity = Polity::new(PolityState::build().finish());
+
+		let outcome = polity.perform(PolityAction::EnterPerson{ person_id: 1, given_weight: 10.into() }).unwrap();
+		assert_eq!(outcome.changes, vec![PolityStateChange::InsertPerson{ person_id: 1, given_weight: 10.into() }]);
+		assert_eq!(polity.person(1), Some(&StoragePerson{ id: 1, given_weight: 10.into() }));
+		assert_eq!(polity.persons().count(), 1);
+
+		let errors = polity.perform(PolityAction::EnterPerson{ person_id: 1, given_weight: 10.into() }).unwrap_err();
+		assert_eq!(errors, vec![PolityActionError::IdConflict{ id: 1, table_kind: TableKind::StoragePerson }]);
+
+		let content = CandidacyContent::Office{ pitch: "".into() };
+		let errors = polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content }).unwrap_err();
+		assert_eq!(errors, vec![PolityActionError::MismatchedKind{ candidacy_id: 10, expected_kind: ElectionKind::Document }]);
+		assert_eq!(polity.candidacy(10), None);
+
+		let content = document(vec![]);
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content }).unwrap();
+		assert_eq!(polity.election_candidacies(0).map(|c| c.id).collect::<Vec<_>>(), vec![10]);
+		assert_eq!(polity.election_winners(0).count(), 0);
+	}
+
+	#[test]
+	fn test_calculate_does_not_apply() {
+		let mut polity = Polity::new(PolityState::build().finish());
+
+		let outcome = polity.calculate(PolityAction::EnterPerson{ person_id: 1, given_weight: 10.into() }).unwrap();
+		assert_eq!(polity.person(1), None);
+
+		polity.apply(&outcome);
+		assert!(polity.person(1).is_some());
+		assert!(polity.election(0).is_some());
+		assert_eq!(polity.elections().count(), 1);
+	}
+
+	#[test]
+	fn test_batches() {
+		let mut polity = Polity::new(PolityState::build().finish());
+		let content = document(vec![]);
+		let allocate = |voter_id: usize, weight: i64| PolityAction::SetAllocations{ voter_id, resource_allocations: vec![], resource_score_allocations: vec![
+			ResourceScoreAllocation{ election_id: 0, approve_weight: weight.into(), disapprove_weight: 0.into(), scores: HashMap::from([(10, 1.into())]), fallback_candidacy_ids: HashMap::new() },
+		] };
+
+		let errors = polity.perform_batch(vec![
+			PolityAction::EnterPerson{ person_id: 1, given_weight: 10.into() },
+			PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 2, election_id: 0, content: content.clone() },
+			PolityAction::EnterPerson{ person_id: 1, given_weight: 10.into() },
+			allocate(1, 20),
+		]).unwrap_err();
+		assert_eq!(errors, vec![
+			BatchActionErrors{ index: 1, errors: vec![PolityActionError::NotFound{ id: 2, table_kind: TableKind::StoragePerson }] },
+			BatchActionErrors{ index: 2, errors: vec![PolityActionError::IdConflict{ id: 1, table_kind: TableKind::StoragePerson }] },
+			// checked against person 1 as entered earlier in the batch
+			BatchActionErrors{ index: 3, errors: vec![PolityActionError::AboveAllowedWeight{ voter_id: 1, found_weight: 20.into(), given_weight: 10.into() }] },
+		]);
+		assert_eq!(polity.persons().count(), 0);
+
+		let outcomes = polity.perform_batch(vec![
+			PolityAction::EnterPerson{ person_id: 1, given_weight: 10.into() },
+			PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content },
+			allocate(1, 10),
+		]).unwrap();
+		assert_eq!(outcomes.len(), 3);
+		assert_eq!(outcomes[0].changes, vec![PolityStateChange::InsertPerson{ person_id: 1, given_weight: 10.into() }]);
+		assert!(polity.candidacy(10).is_some());
+		assert_eq!(polity.state().resource_score_allocation_table[&1].len(), 1);
+	}
+
+	#[test]
+	fn test_out_of_order_actions() {
+		let mut polity = Polity::new(PolityState::build().finish());
+		polity.perform_at(timestamp(10), PolityAction::EnterPerson{ person_id: 1, given_weight: 10.into() }).unwrap();
+
+		let errors = polity.perform_at(timestamp(9), PolityAction::EnterPerson{ person_id: 2, given_weight: 10.into() }).unwrap_err();
+		assert_eq!(errors, vec![PolityActionError::OutOfOrder{ occurred_at: timestamp(9), latest_occurred_at: timestamp(10) }]);
+		assert_eq!(polity.person(2), None);
+
+		let outcome = polity.perform_at(timestamp(10), PolityAction::EnterPerson{ person_id: 2, given_weight: 10.into() }).unwrap();
+		assert_eq!(outcome.changes, vec![PolityStateChange::InsertPerson{ person_id: 2, given_weight: 10.into() }]);
+		polity.perform(PolityAction::EnterPerson{ person_id: 3, given_weight: 10.into() }).unwrap();
+		assert_eq!(polity.state().latest_occurred_at, timestamp(10));
+	}
+
+	#[test]
+	fn test_constitution_at() {
+		let mut polity = Polity::new(PolityState::build().with_resource().finish());
+		polity.perform_at(timestamp(1), PolityAction::EnterPerson{ person_id: 1, given_weight: 100.into() }).unwrap();
+		let bylaws = InputElection{ title: "bylaws".into(), kind: ElectionKind::Document, ..office_election(1) };
+
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content: document_with_body("first", vec![bylaws]) }).unwrap();
+		polity.perform(allocate(1, vec![(0, 10, 50)])).unwrap();
+		for occurred_at in 2..4 { polity.perform_at(timestamp(occurred_at), PolityAction::Recalculate).unwrap(); }
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 20, owner_id: 1, election_id: 1, content: document_with_body("bylaws", vec![]) }).unwrap();
+		polity.perform(allocate(1, vec![(0, 10, 50), (1, 20, 20)])).unwrap();
+		for occurred_at in 4..6 { polity.perform_at(timestamp(occurred_at), PolityAction::Recalculate).unwrap(); }
+		assert_eq!(polity.state().latest_occurred_at, timestamp(5));
+
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 11, owner_id: 1, election_id: 0, content: document_with_body("second", vec![]) }).unwrap();
+		polity.perform(allocate(1, vec![(0, 11, 60)])).unwrap();
+		for occurred_at in 6..8 { polity.perform_at(timestamp(occurred_at), PolityAction::Recalculate).unwrap(); }
+		assert!(polity.election(1).is_none());
+
+		let ids = |node: &ConstitutionNode| (node.enactment.candidacy_id, node.children.iter().map(|child| child.enactment.candidacy_id).collect::<Vec<_>>());
+		assert_eq!(polity.constitution_at(0, timestamp(2)), None);
+		assert_eq!(polity.constitution_at(0, timestamp(3)).map(|node| ids(&node)), Some((10, vec![])));
+		assert_eq!(polity.constitution_at(0, timestamp(6)).map(|node| ids(&node)), Some((10, vec![20])));
+		assert_eq!(polity.constitution_at(0, timestamp(7)).map(|node| ids(&node)), Some((11, vec![])));
+
+		assert_eq!(polity.enactments(1), &[Enactment{ candidacy_id: 20, content: document_with_body("bylaws", vec![]), enacted_at: timestamp(4), replaced_at: Some(timestamp(7)) }]);
+		assert_eq!(polity.enactments(0).iter().map(|e| (e.candidacy_id, e.enacted_at, e.replaced_at)).collect::<Vec<_>>(), vec![(10, timestamp(3), Some(timestamp(7))), (11, timestamp(7), None)]);
+	}
+}
@@ -0,0 +1,167 @@
+use super::*;
+
+// what the next Recalculate would do, worked out against a copy of the state so nothing is committed
+// hypothetical allocations are applied to the copy first, so voters can ask what would happen if they changed their allocations
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecalculationPreview {
+	pub outcome: PolityActionOutcome,
+	pub summary: RecalculationSummary,
+}
+
+// every list is in id order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecalculationSummary {
+	pub winner_changes: Vec<WinnerChange>,
+	// candidacies that would move from nomination into the election stage
+	pub promoted_candidacy_ids: Vec<usize>,
+	pub created_election_ids: Vec<usize>,
+	// elections kept under a new document with its settings
+	pub carried_over_election_ids: Vec<usize>,
+	pub deleted_election_ids: Vec<usize>,
+	pub removed_candidacy_ids: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WinnerChange {
+	pub election_id: usize,
+	pub new_winner_ids: Vec<usize>,
+	pub former_winner_ids: Vec<usize>,
+}
+
+// only SetAllocations actions can be hypothetical, anything else is rejected at its index
+pub fn preview_recalculation(
+	state: &PolityState,
+	hypothetical_allocations: Vec<PolityAction>,
+) -> Result<RecalculationPreview, Vec<BatchActionErrors>> {
+	let batch_errors: Vec<_> = hypothetical_allocations.iter().enumerate()
+		.filter(|(_, action)| !matches!(action, PolityAction::SetAllocations{..}))
+		.map(|(index, _)| BatchActionErrors { index, errors: vec![PolityActionError::NotSetAllocations] })
+		.collect();
+	if !batch_errors.is_empty() {
+		return Err(batch_errors);
+	}
+
+	// the recalculation itself counts as coming right after the hypothetical allocations
+	let recalculation_index = hypothetical_allocations.len();
+	let mut scratch = Polity::new(state.clone());
+	scratch.perform_batch(hypothetical_allocations)?;
+	let outcome = scratch.calculate(PolityAction::Recalculate)
+		.map_err(|errors| vec![BatchActionErrors { index: recalculation_index, errors }])?;
+	let summary = summarize_changes(scratch.state(), &outcome.changes);
+	Ok(RecalculationPreview { outcome, summary })
+}
+
+// compares the changes against the state they'd be applied to
+fn summarize_changes(state: &PolityState, changes: &[PolityStateChange]) -> RecalculationSummary {
+	let mut new_winner_ids = BTreeMap::new();
+	let mut former_winner_ids = BTreeMap::new();
+	let mut promoted_candidacy_ids = BTreeSet::new();
+	let mut created_election_ids = BTreeSet::new();
+	let mut carried_over_election_ids = BTreeSet::new();
+	let mut deleted_election_ids = BTreeSet::new();
+	let mut removed_candidacy_ids = BTreeSet::new();
+
+	for change in changes {
+		match change {
+			PolityStateChange::SetCandidacyStatus{ candidacy_id, status } => {
+				let candidacy = match state.candidacy_table.get(candidacy_id) {
+					Some(candidacy) => candidacy,
+					None => continue,
+				};
+				match (&candidacy.status, status) {
+					(CandidacyStatus::Winner, CandidacyStatus::Winner) => {},
+					(_, CandidacyStatus::Winner) => {
+						new_winner_ids.entry(candidacy.election_id).or_insert_with(BTreeSet::new).insert(*candidacy_id);
+					},
+					(CandidacyStatus::Winner, _) => {
+						former_winner_ids.entry(candidacy.election_id).or_insert_with(BTreeSet::new).insert(*candidacy_id);
+					},
+					(CandidacyStatus::Nomination(_), CandidacyStatus::Election(_)) => {
+						promoted_candidacy_ids.insert(*candidacy_id);
+					},
+					_ => {},
+				}
+			},
+			PolityStateChange::RemoveCandidacy{ candidacy_id, .. } => {
+				let candidacy = match state.candidacy_table.get(candidacy_id) {
+					Some(candidacy) => candidacy,
+					None => continue,
+				};
+				removed_candidacy_ids.insert(*candidacy_id);
+				if candidacy.status == CandidacyStatus::Winner {
+					former_winner_ids.entry(candidacy.election_id).or_insert_with(BTreeSet::new).insert(*candidacy_id);
+				}
+			},
+			PolityStateChange::InsertElection{ election } => { created_election_ids.insert(election.id); },
+			PolityStateChange::ReplaceElection{ election } => { carried_over_election_ids.insert(election.id); },
+			PolityStateChange::RemoveElection{ election_id } => { deleted_election_ids.insert(*election_id); },
+			_ => {},
+		}
+	}
+
+	// a sub election whose id is reused by a new document is both deleted and created, and only the creation is interesting
+	let deleted_election_ids = deleted_election_ids.difference(&created_election_ids).copied().collect();
+
+	let election_ids: BTreeSet<_> = new_winner_ids.keys().chain(former_winner_ids.keys()).copied().collect();
+	let winner_changes = election_ids.into_iter()
+		.map(|election_id| WinnerChange {
+			election_id,
+			new_winner_ids: new_winner_ids.remove(&election_id).unwrap_or_default().into_iter().collect(),
+			former_winner_ids: former_winner_ids.remove(&election_id).unwrap_or_default().into_iter().collect(),
+		})
+		.collect();
+
+	RecalculationSummary {
+		winner_changes,
+		promoted_candidacy_ids: promoted_candidacy_ids.into_iter().collect(),
+		created_election_ids: created_election_ids.into_iter().collect(),
+		carried_over_election_ids: carried_over_election_ids.into_iter().collect(),
+		deleted_election_ids,
+		removed_candidacy_ids: removed_candidacy_ids.into_iter().collect(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_preview_recalculation() {
+		let mut polity = Polity::new(PolityState::build().with_resource().finish());
+		polity.perform(PolityAction::EnterPerson{ person_id: 1, given_weight: 100.into() }).unwrap();
+		let office = InputElection{ nomination_fill_method: NominationFillMethod::Constant(10.into()), ..office_election(1) };
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 10, owner_id: 1, election_id: 0, content: document(vec![office]) }).unwrap();
+		let support = |weight: i64| allocate(1, vec![(0, 10, weight)]);
+		polity.perform(support(30)).unwrap();
+		polity.perform(PolityAction::Recalculate).unwrap();
+
+		let preview = polity.preview_recalculation(vec![]).unwrap();
+		assert_eq!(preview.summary, RecalculationSummary::default());
+
+		let preview = polity.preview_recalculation(vec![support(80)]).unwrap();
+		assert_eq!(preview.summary, RecalculationSummary{
+			winner_changes: vec![WinnerChange{ election_id: 0, new_winner_ids: vec![10], former_winner_ids: vec![] }],
+			created_election_ids: vec![1],
+			..RecalculationSummary::default()
+		});
+		assert_eq!(polity.candidacy(10).unwrap().status, CandidacyStatus::Election(30.into()));
+		assert!(polity.election(1).is_none());
+
+		let errors = polity.preview_recalculation(vec![support(80), PolityAction::Recalculate, support(200)]).unwrap_err();
+		assert_eq!(errors, vec![BatchActionErrors{ index: 1, errors: vec![PolityActionError::NotSetAllocations] }]);
+		let errors = polity.preview_recalculation(vec![support(80), support(200)]).unwrap_err();
+		assert_eq!(errors, vec![BatchActionErrors{
+			index: 1, errors: vec![PolityActionError::AboveAllowedWeight{ voter_id: 1, found_weight: 200.into(), given_weight: 100.into() }],
+		}]);
+
+		// promotion out of nomination once the office election exists
+		polity.perform(support(80)).unwrap();
+		polity.perform(PolityAction::Recalculate).unwrap();
+		polity.perform(PolityAction::EnterCandidacy{ candidacy_id: 20, owner_id: 1, election_id: 1, content: CandidacyContent::Office{ pitch: "".into() } }).unwrap();
+		let preview = polity.preview_recalculation(vec![allocate(1, vec![(0, 10, 80), (1, 20, 10)])]).unwrap();
+		assert_eq!(preview.summary.promoted_candidacy_ids, vec![20]);
+	}
+}